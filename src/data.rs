@@ -0,0 +1,44 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use wahlbergdown::Value;
+
+/// value converts some data into a value, turning objects into maps, and arrays into lists.
+fn value(data: serde_json::Value) -> Result<Value, String> {
+    Ok(match data {
+        serde_json::Value::Null => Value::Nil,
+        serde_json::Value::Bool(b) => Value::Int(if b { 1 } else { 0 }),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Value::Int(i),
+            None => return Err(format!("{} is not an integer", n)),
+        },
+        serde_json::Value::String(s) => Value::Str(s),
+        serde_json::Value::Array(a) => {
+            Value::List(a.into_iter().map(value).collect::<Result<_, _>>()?)
+        }
+        serde_json::Value::Object(o) => Value::Map(
+            o.into_iter()
+                .map(|(k, v)| Ok((k, value(v)?)))
+                .collect::<Result<BTreeMap<_, _>, String>>()?,
+        ),
+    })
+}
+
+/// load reads the top-level keys of a data file, along with their values.
+///
+/// The format of the file depends on its extension: JSON, TOML, or YAML.
+pub fn load(path: &Path) -> Result<Vec<(String, Value)>, String> {
+    let src = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let data: serde_json::Value = match extension {
+        "json" => serde_json::from_str(&src).map_err(|e| e.to_string())?,
+        "toml" => toml::from_str(&src).map_err(|e| e.to_string())?,
+        "yaml" | "yml" => serde_yaml::from_str(&src).map_err(|e| e.to_string())?,
+        _ => return Err("expected a .json, .toml, .yaml, or .yml file".to_string()),
+    };
+    match data {
+        serde_json::Value::Object(o) => o.into_iter().map(|(k, v)| Ok((k, value(v)?))).collect(),
+        _ => Err("expected keys at the top level".to_string()),
+    }
+}
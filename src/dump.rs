@@ -0,0 +1,198 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use wahlbergdown::interpreter::{self, Comment, Definition, Expr, ExprKind, Statement};
+use wahlbergdown::lexer::Lexer;
+use wahlbergdown::parser::{DocumentChunk, Parser};
+use wahlbergdown::span::{Span, Spanned};
+use wahlbergdown::Error;
+
+/// The format used to print the output of the lexer or the parser.
+#[derive(Clone, Copy, Debug)]
+pub enum Format {
+    /// A human readable format, which might change at any time.
+    Text,
+    /// A JSON array, with one element per token or chunk.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown format `{}`, expected `text` or `json`", s)),
+        }
+    }
+}
+
+/// The result of parsing the code inside of a chunk.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ParsedCode {
+    Definition(Definition),
+    Expr(Expr),
+    Block(Vec<Statement>),
+    Error(Error),
+}
+
+/// A chunk of a document, along with the code parsed inside of it.
+#[derive(Debug, Serialize)]
+struct ParsedChunk {
+    #[serde(flatten)]
+    chunk: Spanned<DocumentChunk>,
+    #[serde(flatten)]
+    code: Option<ParsedCode>,
+    /// The comments inside of the code, which parsing ignores.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    comments: Vec<Spanned<Comment>>,
+}
+
+impl ParsedChunk {
+    fn new(chunk: Spanned<DocumentChunk>) -> Self {
+        let comments = match &chunk.item {
+            DocumentChunk::Raw(_) => Vec::new(),
+            DocumentChunk::Comment(c)
+            | DocumentChunk::Interpolate(c)
+            | DocumentChunk::Block { code: c, .. } => interpreter::comments(c),
+        };
+        let code = match &chunk.item {
+            DocumentChunk::Raw(_) => None,
+            DocumentChunk::Comment(c) => Some(match interpreter::parse_definition(c) {
+                Err(e) => ParsedCode::Error(e),
+                Ok(def) => ParsedCode::Definition(def),
+            }),
+            DocumentChunk::Interpolate(c) => Some(match interpreter::parse_expr(c) {
+                Err(e) => ParsedCode::Error(e),
+                Ok(expr) => ParsedCode::Expr(expr),
+            }),
+            DocumentChunk::Block { code, .. } => Some(match interpreter::parse_block(code) {
+                Err(e) => ParsedCode::Error(e),
+                Ok(statements) => ParsedCode::Block(statements),
+            }),
+        };
+        ParsedChunk {
+            chunk,
+            code,
+            comments,
+        }
+    }
+}
+
+/// The indentation used for each level of the trees we print.
+const INDENT: &str = "  ";
+
+fn span_text(span: Span) -> String {
+    format!("{}..{}", span.start, span.end)
+}
+
+fn print_expr_tree(expr: &Expr, depth: usize) {
+    let indent = INDENT.repeat(depth);
+    let span = span_text(expr.span);
+    match &expr.item {
+        ExprKind::Nil => println!("{}{} Nil", indent, span),
+        ExprKind::Int(i) => println!("{}{} Int {}", indent, span, i),
+        ExprKind::Str(s) => println!("{}{} Str {:?}", indent, span, s),
+        ExprKind::Ident(i) => println!("{}{} Ident {}", indent, span, i.0),
+        ExprKind::Call { name: i, args } => {
+            println!("{}{} Call {}", indent, span, i.item.0);
+            for arg in args {
+                print_expr_tree(arg, depth + 1);
+            }
+        }
+    }
+}
+
+fn print_code_tree(code: &ParsedCode, depth: usize) {
+    let indent = INDENT.repeat(depth);
+    match code {
+        ParsedCode::Definition(Definition::Value { name: i, body: e }) => {
+            println!("{}Value {}", indent, i.item.0);
+            print_expr_tree(e, depth + 1);
+        }
+        ParsedCode::Definition(Definition::Func {
+            name: i,
+            args,
+            body: e,
+        }) => {
+            let args: Vec<&str> = args.iter().map(|a| a.item.0.as_str()).collect();
+            println!("{}Func {} ({})", indent, i.item.0, args.join(" "));
+            print_expr_tree(e, depth + 1);
+        }
+        ParsedCode::Definition(Definition::Import {
+            path,
+            path_span: span,
+            alias: name,
+        }) => match name {
+            None => println!("{}{} Import {:?}", indent, span_text(*span), path),
+            Some(n) => println!(
+                "{}{} Import {:?} as {}",
+                indent,
+                span_text(*span),
+                path,
+                n.item.0
+            ),
+        },
+        ParsedCode::Definition(Definition::Export { names }) => {
+            let names: Vec<&str> = names.iter().map(|n| n.item.0.as_str()).collect();
+            println!("{}Export {}", indent, names.join(" "));
+        }
+        ParsedCode::Expr(e) => print_expr_tree(e, depth),
+        ParsedCode::Block(statements) => {
+            for statement in statements {
+                let code = match statement {
+                    Statement::Definition(def) => ParsedCode::Definition(def.clone()),
+                    Statement::Expr(expr) => ParsedCode::Expr(expr.clone()),
+                };
+                print_code_tree(&code, depth);
+            }
+        }
+        ParsedCode::Error(e) => println!("{}{} ERROR: {}", indent, span_text(e.span), e),
+    }
+}
+
+fn print_json(value: &impl Serialize) {
+    let json = serde_json::to_string_pretty(value).expect("failed to serialize output");
+    println!("{}", json);
+}
+
+pub fn lex_and_stop(input_file: &Path, format: Format) {
+    let src = fs::read_to_string(input_file).expect("failed to read input file");
+    let tokens = Lexer::new(&src);
+    match format {
+        Format::Text => {
+            for tok in tokens {
+                println!("{} {:?}", span_text(tok.span), tok.item);
+            }
+        }
+        Format::Json => print_json(&tokens.collect::<Vec<_>>()),
+    }
+}
+
+pub fn parse_and_stop(input_file: &Path, format: Format, deep: bool) {
+    let src = fs::read_to_string(input_file).expect("failed to read input file");
+    let tokens = Lexer::new(&src);
+    let chunks = Parser::new(tokens);
+    match format {
+        Format::Text => {
+            for chunk in chunks {
+                println!("{} {:?}", span_text(chunk.span), chunk.item);
+                if deep {
+                    let parsed = ParsedChunk::new(chunk);
+                    if let Some(code) = parsed.code {
+                        print_code_tree(&code, 1);
+                    }
+                    for c in parsed.comments {
+                        println!("{}{} Comment {}", INDENT, span_text(c.span), c.item.text);
+                    }
+                }
+            }
+        }
+        Format::Json => print_json(&chunks.map(ParsedChunk::new).collect::<Vec<_>>()),
+    }
+}
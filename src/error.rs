@@ -0,0 +1,51 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::span::Span;
+
+/// An Error describes something wrong with some code, and where it is.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Error {
+    pub message: String,
+    pub span: Span,
+}
+
+impl Error {
+    pub fn new(message: String, span: Span) -> Self {
+        Error { message, span }
+    }
+
+    /// msg creates an error which doesn't point at any code yet.
+    ///
+    /// This is meant for native functions: their errors point at the call that failed.
+    pub fn msg(message: impl Into<String>) -> Self {
+        Error::new(message.into(), Span::default())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Errors collects every error that happened while rendering a document.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Errors(pub Vec<Error>);
+
+impl fmt::Display for Errors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, e) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", e)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Errors {}
@@ -0,0 +1,75 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use wahlbergdown::interpreter::{self, pretty};
+use wahlbergdown::lexer::Lexer;
+use wahlbergdown::parser::{DocumentChunk, Parser};
+
+/// The text surrounding the code of a definition comment that fits on one line.
+const COMMENT_OPEN: &str = "<!-- ";
+const COMMENT_CLOSE: &str = " -->";
+
+/// format_code formats a chunk of code, or returns None if it fails to parse.
+fn format_code(chunk: &DocumentChunk) -> Option<String> {
+    match chunk {
+        // Blocks are left as they are, like the rest of the Markdown.
+        DocumentChunk::Raw(_) | DocumentChunk::Block { .. } => None,
+        DocumentChunk::Comment(c) => {
+            let def = interpreter::parse_definition(c).ok()?;
+            let width = pretty::WIDTH - COMMENT_OPEN.len() - COMMENT_CLOSE.len();
+            let code = pretty::definition(&def, &interpreter::comments(c), width);
+            if code.contains('\n') {
+                Some(format!("<!--\n{}\n-->", code))
+            } else {
+                Some(format!("{}{}{}", COMMENT_OPEN, code, COMMENT_CLOSE))
+            }
+        }
+        DocumentChunk::Interpolate(c) => {
+            let expr = interpreter::parse_expr(c).ok()?;
+            let code = pretty::expr(&expr, &interpreter::comments(c));
+            Some(format!("``{}``", code))
+        }
+    }
+}
+
+/// format formats all of the code in a document, leaving everything else untouched.
+fn format(src: &str) -> String {
+    let mut out = String::new();
+    let mut copied = 0;
+    for chunk in Parser::new(Lexer::new(src)) {
+        // An interpolation that never gets closed is left as is.
+        let unclosed = !src[..chunk.span.end].ends_with("``");
+        if matches!(chunk.item, DocumentChunk::Interpolate(_)) && unclosed {
+            continue;
+        }
+        if let Some(code) = format_code(&chunk.item) {
+            out.push_str(&src[copied..chunk.span.start]);
+            out.push_str(&code);
+            copied = chunk.span.end;
+        }
+    }
+    out.push_str(&src[copied..]);
+    out
+}
+
+/// fmt formats files in place, or only checks that they're formatted.
+pub fn fmt(input_files: &[PathBuf], check: bool) {
+    let mut unformatted = 0;
+    for input_file in input_files {
+        let src = fs::read_to_string(input_file).expect("failed to read input file");
+        let formatted = format(&src);
+        if formatted == src {
+            continue;
+        }
+        if check {
+            println!("{} is not formatted", input_file.display());
+            unformatted += 1;
+        } else {
+            fs::write(input_file, formatted).expect("failed to write formatted file");
+        }
+    }
+    if unformatted > 0 {
+        process::exit(1);
+    }
+}
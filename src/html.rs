@@ -0,0 +1,1277 @@
+/// The slot of page templates where the rendered HTML goes.
+pub const CONTENT_SLOT: &str = "{{content}}";
+
+/// page puts some rendered HTML in the content slot of a page template.
+pub fn page(template: &str, content: &str) -> String {
+    template.replace(CONTENT_SLOT, content)
+}
+
+/// render renders Markdown to HTML.
+///
+/// This follows CommonMark for headings, paragraphs, lists, block quotes, thematic breaks,
+/// code, emphasis, links, images, and raw HTML, along with the tables of GitHub Flavored
+/// Markdown. Link reference definitions aren't supported.
+pub fn render(markdown: &str) -> String {
+    let lines: Vec<String> = markdown.lines().map(expand_tabs).collect();
+    let mut out = String::new();
+    render_blocks(&parse_blocks(&lines).0, false, &mut out);
+    out
+}
+
+/// A Block is a block of a document, like a heading or a list.
+#[derive(Debug)]
+enum Block {
+    Heading(usize, String),
+    Paragraph(String),
+    /// A code block, with its info string, like `rust`.
+    Code(String, String),
+    Html(String),
+    Rule,
+    Quote(Vec<Block>),
+    List {
+        /// The number of the first item, for ordered lists.
+        start: Option<u64>,
+        /// Whether the items are close together, in which case their paragraphs
+        /// aren't wrapped in `<p>` tags.
+        tight: bool,
+        items: Vec<Vec<Block>>,
+    },
+    Table {
+        aligns: Vec<Align>,
+        header: Vec<String>,
+        rows: Vec<Vec<String>>,
+    },
+}
+
+/// Align is the alignment of the cells of a column in a table.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Align {
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+impl Align {
+    fn attribute(self) -> &'static str {
+        match self {
+            Align::None => "",
+            Align::Left => " align=\"left\"",
+            Align::Center => " align=\"center\"",
+            Align::Right => " align=\"right\"",
+        }
+    }
+}
+
+/// A Marker starts an item of a list, like `-` or `1.`.
+#[derive(Debug)]
+struct Marker {
+    /// The bullet, like `-`, or for ordered lists, the delimiter after the number, like `.`.
+    kind: char,
+    start: Option<u64>,
+    /// The column where the content of the item starts.
+    offset: usize,
+    /// The content of the item on its first line.
+    rest: String,
+}
+
+/// expand_tabs replaces the tabs indenting a line with spaces, up to the next multiple of 4.
+fn expand_tabs(line: &str) -> String {
+    let mut expanded = String::new();
+    let mut chars = line.chars();
+    for c in chars.by_ref() {
+        match c {
+            ' ' => expanded.push(' '),
+            '\t' => {
+                let width = 4 - expanded.len() % 4;
+                expanded.push_str(&" ".repeat(width));
+            }
+            c => {
+                expanded.push(c);
+                break;
+            }
+        }
+    }
+    expanded.extend(chars);
+    expanded
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+/// strip_indent removes up to some number of spaces of indentation from a line.
+fn strip_indent(line: &str, n: usize) -> String {
+    line[indent(line).min(n)..].to_string()
+}
+
+/// fence finds the character, length, and info string of a fence opening a code block.
+fn fence(line: &str) -> Option<(char, usize, String)> {
+    if indent(line) > 3 {
+        return None;
+    }
+    let line = line.trim_start();
+    let c = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.len() - line.trim_start_matches(c).len();
+    let info = line[len..].trim();
+    if len < 3 || (c == '`' && info.contains('`')) {
+        return None;
+    }
+    Some((c, len, info.to_string()))
+}
+
+/// closes_fence checks whether a line closes a code block opened by a given fence.
+fn closes_fence(line: &str, c: char, len: usize) -> bool {
+    let trimmed = line.trim_start();
+    let closing = trimmed.len() - trimmed.trim_start_matches(c).len();
+    indent(line) <= 3 && closing >= len && trimmed[closing..].trim().is_empty()
+}
+
+fn atx_heading(line: &str) -> Option<(usize, String)> {
+    if indent(line) > 3 {
+        return None;
+    }
+    let line = line.trim();
+    let level = line.len() - line.trim_start_matches('#').len();
+    let rest = &line[level..];
+    if level == 0 || level > 6 || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    // A closing sequence of hashes has to come after a space.
+    let mut text = rest.trim();
+    let without_hashes = text.trim_end_matches('#');
+    if without_hashes.is_empty() || without_hashes.ends_with(' ') {
+        text = without_hashes.trim_end();
+    }
+    Some((level, text.to_string()))
+}
+
+fn is_rule(line: &str) -> bool {
+    if indent(line) > 3 {
+        return false;
+    }
+    let chars: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    chars.len() >= 3 && matches!(chars[0], '-' | '*' | '_') && chars.iter().all(|c| *c == chars[0])
+}
+
+/// setext_underline finds the level of the heading a line underlines, like `===`.
+fn setext_underline(line: &str) -> Option<usize> {
+    if indent(line) > 3 {
+        return None;
+    }
+    let line = line.trim();
+    match line.chars().next()? {
+        '=' if line.chars().all(|c| c == '=') => Some(1),
+        '-' if line.chars().all(|c| c == '-') => Some(2),
+        _ => None,
+    }
+}
+
+/// quote finds the rest of a line of a block quote, after the `>`.
+fn quote(line: &str) -> Option<String> {
+    if indent(line) > 3 {
+        return None;
+    }
+    let rest = line.trim_start().strip_prefix('>')?;
+    Some(rest.strip_prefix(' ').unwrap_or(rest).to_string())
+}
+
+fn list_marker(line: &str) -> Option<Marker> {
+    let ind = indent(line);
+    if ind > 3 {
+        return None;
+    }
+    let line = &line[ind..];
+    let (kind, start, width) = match line.chars().next()? {
+        c @ ('-' | '*' | '+') => (c, None, 1),
+        '0'..='9' => {
+            let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let kind = line[digits..]
+                .chars()
+                .next()
+                .filter(|c| *c == '.' || *c == ')')?;
+            if digits > 9 {
+                return None;
+            }
+            (kind, Some(line[..digits].parse().ok()?), digits + 1)
+        }
+        _ => return None,
+    };
+    let after = &line[width..];
+    if after.trim().is_empty() {
+        return Some(Marker {
+            kind,
+            start,
+            offset: ind + width + 1,
+            rest: String::new(),
+        });
+    }
+    let spaces = indent(after);
+    if spaces == 0 {
+        return None;
+    }
+    // With 5 spaces or more, the content is indented code, so it only counts one.
+    let spaces = if spaces > 4 { 1 } else { spaces };
+    Some(Marker {
+        kind,
+        start,
+        offset: ind + width + spaces,
+        rest: after[spaces..].to_string(),
+    })
+}
+
+/// The tags which start HTML blocks wherever they are, even in the middle of a paragraph.
+const BLOCK_TAGS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "base",
+    "basefont",
+    "blockquote",
+    "body",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "iframe",
+    "legend",
+    "li",
+    "link",
+    "main",
+    "menu",
+    "menuitem",
+    "nav",
+    "noframes",
+    "ol",
+    "optgroup",
+    "option",
+    "p",
+    "param",
+    "search",
+    "section",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+];
+
+/// The tags whose HTML blocks go on until they're closed, even past blank lines.
+const RAW_TAGS: &[(&str, &str)] = &[
+    ("pre", "</pre>"),
+    ("script", "</script>"),
+    ("style", "</style>"),
+    ("textarea", "</textarea>"),
+];
+
+/// HtmlEnd tells which line ends an HTML block.
+enum HtmlEnd {
+    /// The block ends before a blank line.
+    Blank,
+    /// The block ends with the line containing this text, ignoring case.
+    Containing(&'static str),
+}
+
+/// html_start checks whether a line starts an HTML block, following CommonMark.
+///
+/// Comments and block tags, like `<div>`, start one anywhere. Other tags only do when
+/// they're alone on their line and `interrupting` isn't set, since they can't interrupt
+/// a paragraph. Otherwise, they're inline HTML.
+fn html_start(line: &str, interrupting: bool) -> Option<HtmlEnd> {
+    if indent(line) > 3 {
+        return None;
+    }
+    let line = line.trim();
+    let rest = line.strip_prefix('<')?;
+    for (start, end) in [("!--", "-->"), ("?", "?>"), ("![CDATA[", "]]>")] {
+        if rest.starts_with(start) {
+            return Some(HtmlEnd::Containing(end));
+        }
+    }
+    if rest.starts_with('!') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return Some(HtmlEnd::Containing(">"));
+    }
+
+    let closing = rest.starts_with('/');
+    let rest = rest.strip_prefix('/').unwrap_or(rest);
+    let len = rest
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+        .unwrap_or(rest.len());
+    let name = rest[..len].to_ascii_lowercase();
+    let after = &rest[len..];
+    let ends_name = after.is_empty() || after.starts_with(|c: char| c.is_whitespace() || c == '>');
+    if let Some((_, end)) = RAW_TAGS.iter().find(|(tag, _)| *tag == name) {
+        return (!closing && ends_name).then_some(HtmlEnd::Containing(end));
+    }
+    if BLOCK_TAGS.contains(&name.as_str()) && (ends_name || after.starts_with("/>")) {
+        return Some(HtmlEnd::Blank);
+    }
+    // Autolinks, like `<http://x.com>`, become links rather than staying as they are.
+    let chars: Vec<char> = line.chars().collect();
+    let alone = angle_bracket(&chars).is_some_and(|(html, len)| len == chars.len() && html == line);
+    (alone && !interrupting).then_some(HtmlEnd::Blank)
+}
+
+/// starts_block checks whether a line starts a block which can interrupt a paragraph.
+fn starts_block(line: &str) -> bool {
+    atx_heading(line).is_some()
+        || fence(line).is_some()
+        || is_rule(line)
+        || quote(line).is_some()
+        || html_start(line, true).is_some()
+        || list_marker(line)
+            .is_some_and(|m| !m.rest.trim().is_empty() && m.start.is_none_or(|s| s == 1))
+}
+
+/// split_row splits a row of a table into its cells.
+///
+/// Pipes escaped with a backslash don't split cells, and lose their backslash, even
+/// in code spans.
+fn split_row(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = match line.strip_suffix('|') {
+        Some(l) if !l.ends_with('\\') => l,
+        _ => line,
+    };
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('|') => cell.push('|'),
+                next => {
+                    cell.push(c);
+                    cell.extend(next);
+                }
+            },
+            '|' => cells.push(std::mem::take(&mut cell).trim().to_string()),
+            c => cell.push(c),
+        }
+    }
+    cells.push(cell.trim().to_string());
+    cells
+}
+
+/// table_delimiter finds the alignment of the columns of a table, from the row under its header.
+fn table_delimiter(line: &str) -> Option<Vec<Align>> {
+    if indent(line) > 3 || !line.contains('-') {
+        return None;
+    }
+    split_row(line)
+        .iter()
+        .map(|cell| {
+            let left = cell.starts_with(':');
+            let right = cell.ends_with(':');
+            let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                return None;
+            }
+            Some(match (left, right) {
+                (false, false) => Align::None,
+                (true, false) => Align::Left,
+                (true, true) => Align::Center,
+                (false, true) => Align::Right,
+            })
+        })
+        .collect()
+}
+
+/// parse_blocks parses lines into blocks.
+///
+/// This also returns whether there were blank lines between the blocks, which makes
+/// the list items containing them loose.
+fn parse_blocks(lines: &[String]) -> (Vec<Block>, bool) {
+    let mut blocks = Vec::new();
+    let mut gaps = false;
+    let mut blank = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = &lines[i];
+        if is_blank(line) {
+            blank = true;
+            i += 1;
+            continue;
+        }
+        gaps |= blank && !blocks.is_empty();
+        blank = false;
+
+        if indent(line) >= 4 {
+            let mut code = Vec::new();
+            while i < lines.len() && (is_blank(&lines[i]) || indent(&lines[i]) >= 4) {
+                code.push(strip_indent(&lines[i], 4));
+                i += 1;
+            }
+            while code.last().is_some_and(|l| is_blank(l)) {
+                code.pop();
+            }
+            blocks.push(Block::Code(String::new(), code.join("\n") + "\n"));
+        } else if let Some((c, len, info)) = fence(line) {
+            let fence_indent = indent(line);
+            let mut code = String::new();
+            i += 1;
+            while i < lines.len() {
+                if closes_fence(&lines[i], c, len) {
+                    i += 1;
+                    break;
+                }
+                code.push_str(&strip_indent(&lines[i], fence_indent));
+                code.push('\n');
+                i += 1;
+            }
+            blocks.push(Block::Code(info, code));
+        } else if let Some((level, text)) = atx_heading(line) {
+            blocks.push(Block::Heading(level, text));
+            i += 1;
+        } else if is_rule(line) {
+            blocks.push(Block::Rule);
+            i += 1;
+        } else if quote(line).is_some() {
+            let mut quoted: Vec<String> = Vec::new();
+            while i < lines.len() {
+                match quote(&lines[i]) {
+                    Some(rest) => quoted.push(rest),
+                    // Paragraphs can go on without the `>`.
+                    None if !is_blank(&lines[i])
+                        && !starts_block(&lines[i])
+                        && quoted.last().is_some_and(|l| !is_blank(l)) =>
+                    {
+                        quoted.push(lines[i].clone())
+                    }
+                    None => break,
+                }
+                i += 1;
+            }
+            blocks.push(Block::Quote(parse_blocks(&quoted).0));
+        } else if list_marker(line).is_some() {
+            let (list, next) = parse_list(lines, i);
+            blocks.push(list);
+            i = next;
+        } else if let Some(end) = html_start(line, false) {
+            let mut html = Vec::new();
+            while i < lines.len() {
+                let line = lines[i].as_str();
+                if let HtmlEnd::Blank = end {
+                    if is_blank(line) {
+                        break;
+                    }
+                }
+                html.push(line);
+                i += 1;
+                if let HtmlEnd::Containing(end) = end {
+                    if line.to_ascii_lowercase().contains(end) {
+                        break;
+                    }
+                }
+            }
+            blocks.push(Block::Html(html.join("\n") + "\n"));
+        } else if let Some(aligns) = lines
+            .get(i + 1)
+            .filter(|_| line.contains('|'))
+            .and_then(|l| table_delimiter(l))
+            .filter(|aligns| aligns.len() == split_row(line).len())
+        {
+            let header = split_row(line);
+            let mut rows = Vec::new();
+            i += 2;
+            while i < lines.len()
+                && !is_blank(&lines[i])
+                && !starts_block(&lines[i])
+                && lines[i].contains('|')
+            {
+                let mut row = split_row(&lines[i]);
+                row.resize(header.len(), String::new());
+                rows.push(row);
+                i += 1;
+            }
+            blocks.push(Block::Table {
+                aligns,
+                header,
+                rows,
+            });
+        } else {
+            let mut text = vec![line.trim_start()];
+            i += 1;
+            let mut heading = None;
+            while i < lines.len() && !is_blank(&lines[i]) {
+                if let Some(level) = setext_underline(&lines[i]) {
+                    heading = Some(level);
+                    i += 1;
+                    break;
+                }
+                if starts_block(&lines[i]) {
+                    break;
+                }
+                text.push(lines[i].trim_start());
+                i += 1;
+            }
+            let text = text.join("\n").trim_end().to_string();
+            blocks.push(match heading {
+                Some(level) => Block::Heading(level, text),
+                None => Block::Paragraph(text),
+            });
+        }
+    }
+    (blocks, gaps)
+}
+
+/// parse_list parses a list starting at some line, returning it with the line after it.
+fn parse_list(lines: &[String], mut i: usize) -> (Block, usize) {
+    let first = list_marker(&lines[i]).unwrap();
+    let mut items = Vec::new();
+    let mut tight = true;
+    while let Some(marker) = lines.get(i).and_then(|l| list_marker(l)) {
+        if marker.kind != first.kind || is_rule(&lines[i]) {
+            break;
+        }
+        let mut item = vec![marker.rest];
+        i += 1;
+        while i < lines.len() {
+            let line = &lines[i];
+            if is_blank(line) {
+                item.push(String::new());
+            } else if indent(line) >= marker.offset {
+                item.push(strip_indent(line, marker.offset));
+            } else if item.last().is_some_and(|l| !is_blank(l))
+                && !starts_block(line)
+                && list_marker(line).is_none()
+            {
+                // Paragraphs can go on without being indented.
+                item.push(line.trim_start().to_string());
+            } else {
+                break;
+            }
+            i += 1;
+        }
+        let mut trailing_blank = false;
+        while item.len() > 1 && item.last().is_some_and(|l| is_blank(l)) {
+            item.pop();
+            trailing_blank = true;
+        }
+        let (blocks, gaps) = parse_blocks(&item);
+        tight &= !gaps;
+        items.push(blocks);
+        let next_item = lines
+            .get(i)
+            .and_then(|l| list_marker(l))
+            .is_some_and(|m| m.kind == first.kind);
+        if trailing_blank && next_item {
+            tight = false;
+        }
+    }
+    let list = Block::List {
+        start: first.start,
+        tight,
+        items,
+    };
+    (list, i)
+}
+
+fn escape_char(out: &mut String, c: char) {
+    match c {
+        '&' => out.push_str("&amp;"),
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        '"' => out.push_str("&quot;"),
+        c => out.push(c),
+    }
+}
+
+/// escape escapes the characters of some text which have a meaning in HTML.
+pub fn escape(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        escape_char(&mut out, c);
+    }
+    out
+}
+
+/// unescape removes the backslashes escaping punctuation, like in link destinations.
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' && chars.peek().is_some_and(char::is_ascii_punctuation) {
+            out.extend(chars.next());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut out = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// A Node is a piece of inline content, while looking for emphasis.
+#[derive(Debug)]
+enum Node {
+    Html(String),
+    /// A run of `*` or `_`, which might open or close emphasis.
+    Delim {
+        c: char,
+        count: usize,
+        open: bool,
+        close: bool,
+    },
+}
+
+impl Node {
+    fn push_to(&self, out: &mut String) {
+        match self {
+            Node::Html(s) => out.push_str(s),
+            Node::Delim { c, count, .. } => out.extend(std::iter::repeat_n(*c, *count)),
+        }
+    }
+}
+
+fn is_punctuation(c: Option<char>) -> bool {
+    c.is_some_and(|c| c.is_ascii_punctuation() || (!c.is_ascii() && !c.is_alphanumeric()))
+}
+
+/// The start and end of lines count as whitespace around delimiters.
+fn is_space(c: Option<char>) -> bool {
+    c.is_none_or(char::is_whitespace)
+}
+
+/// entity finds the length of an HTML entity, like `&amp;`, at the start of some text.
+fn entity(chars: &[char]) -> Option<usize> {
+    let end = chars.iter().take(34).position(|c| *c == ';')?;
+    let name: String = chars[1..end].iter().collect();
+    let valid = match name.strip_prefix('#') {
+        Some(n) => match n.strip_prefix(['x', 'X']) {
+            Some(hex) => (1..=6).contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit()),
+            None => (1..=7).contains(&n.len()) && n.chars().all(|c| c.is_ascii_digit()),
+        },
+        None => {
+            name.starts_with(|c: char| c.is_ascii_alphabetic())
+                && name.chars().all(|c| c.is_ascii_alphanumeric())
+        }
+    };
+    if valid {
+        Some(end + 1)
+    } else {
+        None
+    }
+}
+
+/// angle_bracket finds an autolink or some raw HTML, like a tag or a comment, at the
+/// start of some text, returning its HTML and its length.
+fn angle_bracket(chars: &[char]) -> Option<(String, usize)> {
+    let text: String = chars.iter().collect();
+    if text.starts_with("<!--") {
+        let end = text.find("-->")? + 3;
+        return Some((text[..end].to_string(), text[..end].chars().count()));
+    }
+    let end = text.find('>')?;
+    let inside = &text[1..end];
+    let len = inside.chars().count() + 2;
+    let scheme = inside.split(':').next().unwrap_or("");
+    if inside.contains(':')
+        && scheme.len() >= 2
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+.-".contains(c))
+        && !inside.contains(|c: char| c.is_whitespace() || c == '<')
+    {
+        let href = escape(inside);
+        return Some((format!("<a href=\"{}\">{}</a>", href, href), len));
+    }
+    if inside.contains('@') && !inside.contains(|c: char| c.is_whitespace() || c == '<') {
+        let email = escape(inside);
+        return Some((format!("<a href=\"mailto:{}\">{}</a>", email, email), len));
+    }
+    let name = inside.strip_prefix('/').unwrap_or(inside);
+    let tag_name: String = name
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    let after = &name[tag_name.len()..];
+    if tag_name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && (after.is_empty() || after.starts_with(char::is_whitespace) || after == "/")
+        && !inside.contains('<')
+    {
+        return Some((text[..end + 1].to_string(), len));
+    }
+    None
+}
+
+/// link parses a link or an image at the start of some text, after its `[` or `![`,
+/// returning its HTML and how many characters it takes, including the brackets.
+fn link(chars: &[char], image: bool) -> Option<(String, usize)> {
+    let mut depth = 0;
+    let mut i = 0;
+    let close = loop {
+        match chars.get(i)? {
+            '\\' => i += 1,
+            '[' => depth += 1,
+            ']' if depth == 0 => break i,
+            ']' => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    };
+    if chars.get(close + 1) != Some(&'(') {
+        return None;
+    }
+    let mut i = close + 2;
+    while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+        i += 1;
+    }
+    let mut dest = String::new();
+    if chars.get(i) == Some(&'<') {
+        i += 1;
+        loop {
+            match chars.get(i)? {
+                '>' => break,
+                '\n' | '<' => return None,
+                c => dest.push(*c),
+            }
+            i += 1;
+        }
+        i += 1;
+    } else {
+        let mut parens = 0;
+        while let Some(&c) = chars.get(i) {
+            match c {
+                c if c.is_whitespace() => break,
+                '(' => parens += 1,
+                ')' if parens == 0 => break,
+                ')' => parens -= 1,
+                '\\' => {
+                    dest.push(c);
+                    i += 1;
+                    dest.extend(chars.get(i));
+                    i += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            dest.push(c);
+            i += 1;
+        }
+    }
+    while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+        i += 1;
+    }
+    let mut title = None;
+    if let Some(&open) = chars.get(i).filter(|c| matches!(c, '"' | '\'' | '(')) {
+        let end = if open == '(' { ')' } else { open };
+        let mut t = String::new();
+        i += 1;
+        loop {
+            match *chars.get(i)? {
+                c if c == end => break,
+                '\\' if chars.get(i + 1).is_some_and(char::is_ascii_punctuation) => {
+                    t.push(chars[i + 1]);
+                    i += 1;
+                }
+                c => t.push(c),
+            }
+            i += 1;
+        }
+        i += 1;
+        title = Some(t);
+        while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+            i += 1;
+        }
+    }
+    if chars.get(i) != Some(&')') {
+        return None;
+    }
+    let text: String = chars[..close].iter().collect();
+    let dest = escape(&unescape(&dest));
+    let title = title.map_or(String::new(), |t| format!(" title=\"{}\"", escape(&t)));
+    let html = if image {
+        let alt = escape(&strip_tags(&inline(&text)));
+        format!("<img src=\"{}\" alt=\"{}\"{} />", dest, alt, title)
+    } else {
+        format!("<a href=\"{}\"{}>{}</a>", dest, title, inline(&text))
+    };
+    Some((html, i + 1))
+}
+
+/// inline renders the inline content of a block, like emphasis and links.
+fn inline(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut nodes = Vec::new();
+    let mut buf = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            '\\' if next.is_some_and(|c| c.is_ascii_punctuation()) => {
+                escape_char(&mut buf, next.unwrap());
+                i += 2;
+            }
+            '\\' if next == Some('\n') => {
+                buf.push_str("<br />\n");
+                i += 2;
+            }
+            '\n' => {
+                let trimmed = buf.trim_end_matches(' ').len();
+                let hard = buf.len() - trimmed >= 2;
+                buf.truncate(trimmed);
+                buf.push_str(if hard { "<br />\n" } else { "\n" });
+                i += 1;
+            }
+            '`' => {
+                let ticks = chars[i..].iter().take_while(|c| **c == '`').count();
+                let start = i + ticks;
+                let mut j = start;
+                let mut end = None;
+                while j < chars.len() {
+                    let run = chars[j..].iter().take_while(|c| **c == '`').count();
+                    if run == ticks {
+                        end = Some(j);
+                        break;
+                    }
+                    j += run.max(1);
+                }
+                match end {
+                    None => {
+                        buf.push_str(&"`".repeat(ticks));
+                        i = start;
+                    }
+                    Some(end) => {
+                        let code: String = chars[start..end]
+                            .iter()
+                            .map(|c| if *c == '\n' { ' ' } else { *c })
+                            .collect();
+                        let code = match code.strip_prefix(' ').and_then(|c| c.strip_suffix(' ')) {
+                            Some(stripped) if !code.trim().is_empty() => stripped,
+                            _ => &code,
+                        };
+                        buf.push_str(&format!("<code>{}</code>", escape(code)));
+                        i = end + ticks;
+                    }
+                }
+            }
+            '*' | '_' => {
+                let count = chars[i..].iter().take_while(|x| **x == c).count();
+                let before = if i == 0 { None } else { Some(chars[i - 1]) };
+                let after = chars.get(i + count).copied();
+                let left = !is_space(after)
+                    && (!is_punctuation(after) || is_space(before) || is_punctuation(before));
+                let right = !is_space(before)
+                    && (!is_punctuation(before) || is_space(after) || is_punctuation(after));
+                let (open, close) = if c == '*' {
+                    (left, right)
+                } else {
+                    (
+                        left && (!right || is_punctuation(before)),
+                        right && (!left || is_punctuation(after)),
+                    )
+                };
+                nodes.push(Node::Html(std::mem::take(&mut buf)));
+                nodes.push(Node::Delim {
+                    c,
+                    count,
+                    open,
+                    close,
+                });
+                i += count;
+            }
+            '!' if next == Some('[') => match link(&chars[i + 2..], true) {
+                Some((html, len)) => {
+                    buf.push_str(&html);
+                    i += len + 2;
+                }
+                None => {
+                    buf.push('!');
+                    i += 1;
+                }
+            },
+            '[' => match link(&chars[i + 1..], false) {
+                Some((html, len)) => {
+                    buf.push_str(&html);
+                    i += len + 1;
+                }
+                None => {
+                    buf.push('[');
+                    i += 1;
+                }
+            },
+            '<' => match angle_bracket(&chars[i..]) {
+                Some((html, len)) => {
+                    buf.push_str(&html);
+                    i += len;
+                }
+                None => {
+                    buf.push_str("&lt;");
+                    i += 1;
+                }
+            },
+            '&' => match entity(&chars[i..]) {
+                Some(len) => {
+                    buf.extend(&chars[i..i + len]);
+                    i += len;
+                }
+                None => {
+                    buf.push_str("&amp;");
+                    i += 1;
+                }
+            },
+            c => {
+                escape_char(&mut buf, c);
+                i += 1;
+            }
+        }
+    }
+    nodes.push(Node::Html(buf));
+    emphasis(nodes)
+}
+
+/// emphasis matches the runs of `*` and `_` which open and close emphasis, as in CommonMark.
+fn emphasis(mut nodes: Vec<Node>) -> String {
+    let mut i = 0;
+    while i < nodes.len() {
+        let (c, count, open) = match nodes[i] {
+            Node::Delim {
+                c,
+                count,
+                open,
+                close: true,
+            } if count > 0 => (c, count, open),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let opener = (0..i).rev().find(|j| match nodes[*j] {
+            Node::Delim {
+                c: oc,
+                count: ocount,
+                open: true,
+                close: oclose,
+            } => {
+                // Runs which can both open and close only match if their lengths don't add
+                // up to a multiple of 3, unless both are.
+                let rule_of_3 = (oclose || open)
+                    && (ocount + count) % 3 == 0
+                    && !(ocount % 3 == 0 && count % 3 == 0);
+                oc == c && ocount > 0 && !rule_of_3
+            }
+            _ => false,
+        });
+        let j = match opener {
+            None => {
+                i += 1;
+                continue;
+            }
+            Some(j) => j,
+        };
+        let used = match nodes[j] {
+            Node::Delim { count: ocount, .. } if ocount >= 2 && count >= 2 => 2,
+            _ => 1,
+        };
+        for n in [j, i] {
+            if let Node::Delim { count, .. } = &mut nodes[n] {
+                *count -= used;
+            }
+        }
+        let tag = if used == 2 { "strong" } else { "em" };
+        let mut html = format!("<{}>", tag);
+        for node in nodes.drain(j + 1..i) {
+            node.push_to(&mut html);
+        }
+        html.push_str(&format!("</{}>", tag));
+        nodes.insert(j + 1, Node::Html(html));
+        i = j + 2;
+    }
+    let mut out = String::new();
+    for node in &nodes {
+        node.push_to(&mut out);
+    }
+    out
+}
+
+fn render_blocks(blocks: &[Block], tight: bool, out: &mut String) {
+    for block in blocks {
+        match block {
+            Block::Heading(level, text) => {
+                out.push_str(&format!("<h{}>{}</h{}>\n", level, inline(text), level))
+            }
+            Block::Paragraph(text) if tight => out.push_str(&inline(text)),
+            Block::Paragraph(text) => out.push_str(&format!("<p>{}</p>\n", inline(text))),
+            Block::Code(info, code) => {
+                match info.split_whitespace().next() {
+                    None => out.push_str("<pre><code>"),
+                    Some(lang) => out.push_str(&format!(
+                        "<pre><code class=\"language-{}\">",
+                        escape(&unescape(lang))
+                    )),
+                }
+                out.push_str(&escape(code));
+                out.push_str("</code></pre>\n");
+            }
+            Block::Html(html) => out.push_str(html),
+            Block::Rule => out.push_str("<hr />\n"),
+            Block::Quote(blocks) => {
+                out.push_str("<blockquote>\n");
+                render_blocks(blocks, false, out);
+                out.push_str("</blockquote>\n");
+            }
+            Block::List {
+                start,
+                tight,
+                items,
+            } => {
+                let tag = match start {
+                    None => "ul",
+                    Some(_) => "ol",
+                };
+                match start {
+                    Some(n) if *n != 1 => out.push_str(&format!("<ol start=\"{}\">\n", n)),
+                    _ => out.push_str(&format!("<{}>\n", tag)),
+                }
+                for item in items {
+                    out.push_str("<li>");
+                    for block in item {
+                        let inline = *tight && matches!(block, Block::Paragraph(_));
+                        if !inline && !out.ends_with('\n') {
+                            out.push('\n');
+                        }
+                        render_blocks(std::slice::from_ref(block), *tight, out);
+                    }
+                    out.push_str("</li>\n");
+                }
+                out.push_str(&format!("</{}>\n", tag));
+            }
+            Block::Table {
+                aligns,
+                header,
+                rows,
+            } => {
+                out.push_str("<table>\n<thead>\n<tr>\n");
+                for (cell, align) in header.iter().zip(aligns) {
+                    out.push_str(&format!("<th{}>{}</th>\n", align.attribute(), inline(cell)));
+                }
+                out.push_str("</tr>\n</thead>\n");
+                if !rows.is_empty() {
+                    out.push_str("<tbody>\n");
+                    for row in rows {
+                        out.push_str("<tr>\n");
+                        for (cell, align) in row.iter().zip(aligns) {
+                            out.push_str(&format!(
+                                "<td{}>{}</td>\n",
+                                align.attribute(),
+                                inline(cell)
+                            ));
+                        }
+                        out.push_str("</tr>\n");
+                    }
+                    out.push_str("</tbody>\n");
+                }
+                out.push_str("</table>\n");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headings() {
+        assert_eq!(
+            render("# Title\n## *Sub* ##"),
+            "<h1>Title</h1>\n<h2><em>Sub</em></h2>\n"
+        );
+        assert_eq!(
+            render("Title\n=====\n\nSub\n---"),
+            "<h1>Title</h1>\n<h2>Sub</h2>\n"
+        );
+        assert_eq!(render("#not a heading"), "<p>#not a heading</p>\n");
+    }
+
+    #[test]
+    fn paragraphs_and_breaks() {
+        assert_eq!(render("a\nb\n\nc"), "<p>a\nb</p>\n<p>c</p>\n");
+        assert_eq!(
+            render("line  \nbreak\\\nagain"),
+            "<p>line<br />\nbreak<br />\nagain</p>\n"
+        );
+        assert_eq!(render("a\n\n---\n\n***"), "<p>a</p>\n<hr />\n<hr />\n");
+    }
+
+    #[test]
+    fn emphasis() {
+        assert_eq!(
+            render("*em* **strong** ***both***"),
+            "<p><em>em</em> <strong>strong</strong> <em><strong>both</strong></em></p>\n"
+        );
+        assert_eq!(
+            render("_a_b_ snake_case_name"),
+            "<p><em>a_b</em> snake_case_name</p>\n"
+        );
+        assert_eq!(render("**a*b"), "<p>**a*b</p>\n");
+        assert_eq!(
+            render("* not emphasis *"),
+            "<ul>\n<li>not emphasis *</li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn escapes_and_entities() {
+        assert_eq!(
+            render("a\\*b\\* &amp; &copy; 1 < 2 & 3"),
+            "<p>a*b* &amp; &copy; 1 &lt; 2 &amp; 3</p>\n"
+        );
+        assert_eq!(render("\\<b\\>"), "<p>&lt;b&gt;</p>\n");
+    }
+
+    #[test]
+    fn code() {
+        assert_eq!(
+            render("`a` `` b`c `` `<x>`"),
+            "<p><code>a</code> <code>b`c</code> <code>&lt;x&gt;</code></p>\n"
+        );
+        assert_eq!(
+            render("```rust\nfn <x>\n```\n\n    indented\n"),
+            "<pre><code class=\"language-rust\">fn &lt;x&gt;\n</code></pre>\n<pre><code>indented\n</code></pre>\n"
+        );
+        assert_eq!(
+            render("```\nnever closed"),
+            "<pre><code>never closed\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn links_and_images() {
+        assert_eq!(
+            render("[a *link*](http://x.com \"T\") ![alt *x*](i.png)"),
+            "<p><a href=\"http://x.com\" title=\"T\">a <em>link</em></a> <img src=\"i.png\" alt=\"alt x\" /></p>\n"
+        );
+        assert_eq!(
+            render("<http://auto.link>"),
+            "<p><a href=\"http://auto.link\">http://auto.link</a></p>\n"
+        );
+        assert_eq!(render("[not a link] x"), "<p>[not a link] x</p>\n");
+    }
+
+    #[test]
+    fn html() {
+        assert_eq!(
+            render("<div>\n*not emphasis*\n</div>\n\nafter <b>raw</b>"),
+            "<div>\n*not emphasis*\n</div>\n<p>after <b>raw</b></p>\n"
+        );
+        assert_eq!(render("<!-- a\n\nb -->"), "<!-- a\n\nb -->\n");
+        assert_eq!(render("<br/>\n</p>"), "<br/>\n</p>\n");
+        assert_eq!(
+            render("Intro\n<b>Note</b>: *this* matters"),
+            "<p>Intro\n<b>Note</b>: <em>this</em> matters</p>\n"
+        );
+        assert_eq!(
+            render("<b>Note</b>: *this* matters"),
+            "<p><b>Note</b>: <em>this</em> matters</p>\n"
+        );
+        assert_eq!(render("Intro\n<span>\nx"), "<p>Intro\n<span>\nx</p>\n");
+        assert_eq!(
+            render("<span class=\"x\">\n*x*\n\nIntro\n<div>\n*x*"),
+            "<span class=\"x\">\n*x*\n<p>Intro</p>\n<div>\n*x*\n"
+        );
+        assert_eq!(
+            render("<pre>\na\n\nb\n</PRE>\nafter"),
+            "<pre>\na\n\nb\n</PRE>\n<p>after</p>\n"
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            render("- one\n- two"),
+            "<ul>\n<li>one</li>\n<li>two</li>\n</ul>\n"
+        );
+        assert_eq!(
+            render("1. a\n\n2. b"),
+            "<ol>\n<li>\n<p>a</p>\n</li>\n<li>\n<p>b</p>\n</li>\n</ol>\n"
+        );
+        assert_eq!(render("3) c"), "<ol start=\"3\">\n<li>c</li>\n</ol>\n");
+        assert_eq!(
+            render("- a\n  - b\n- c"),
+            "<ul>\n<li>a\n<ul>\n<li>b</li>\n</ul>\n</li>\n<li>c</li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(
+            render("> quoted\ncontinued\n> > nested"),
+            "<blockquote>\n<p>quoted\ncontinued</p>\n<blockquote>\n<p>nested</p>\n</blockquote>\n</blockquote>\n"
+        );
+    }
+
+    #[test]
+    fn tables() {
+        assert_eq!(
+            render("| a | b | c |\n|:--|:-:|--:|\n| 1 | `x\\|y` | 3 |\n| 4 |"),
+            "<table>\n<thead>\n<tr>\n<th align=\"left\">a</th>\n<th align=\"center\">b</th>\n\
+             <th align=\"right\">c</th>\n</tr>\n</thead>\n<tbody>\n<tr>\n<td align=\"left\">1</td>\n\
+             <td align=\"center\"><code>x|y</code></td>\n<td align=\"right\">3</td>\n</tr>\n<tr>\n\
+             <td align=\"left\">4</td>\n<td align=\"center\"></td>\n<td align=\"right\"></td>\n\
+             </tr>\n</tbody>\n</table>\n"
+        );
+        assert_eq!(render("a | b\n--- | x"), "<p>a | b\n--- | x</p>\n");
+    }
+
+    #[test]
+    fn pages() {
+        assert_eq!(
+            page("<body>{{content}}</body>", "<p>x</p>"),
+            "<body><p>x</p></body>"
+        );
+        assert_eq!(
+            escape("<a href=\"x\">&</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use crate::error::Error;
+use crate::interpreter::{Arity, Interpreter, Value, MAX_VALUE_DEPTH};
+use crate::span::Span;
+
+pub(super) fn int(x: &Value) -> Option<i64> {
+    match x {
+        Value::Int(i) => Some(*i),
+        _ => None,
+    }
+}
+
+pub(super) fn string(x: &Value) -> Option<&str> {
+    match x {
+        Value::Str(s) => Some(s),
+        _ => None,
+    }
+}
+
+fn map(x: &Value) -> Option<&BTreeMap<String, Value>> {
+    match x {
+        Value::Map(m) => Some(m),
+        _ => None,
+    }
+}
+
+pub(super) fn items(x: &Value) -> Option<&[Value]> {
+    match x {
+        Value::List(l) => Some(l),
+        _ => None,
+    }
+}
+
+fn bool_value(x: bool) -> Value {
+    Value::Int(if x { 1 } else { 0 })
+}
+
+/// accumulate combines the values of the arguments of a function, one at a time.
+///
+/// If some argument can't be extracted, the result is nil.
+fn accumulate<'a, A, T>(
+    args: &'a [Value],
+    extract: impl Fn(&'a Value) -> Option<T>,
+    init: A,
+    combine: impl Fn(A, T) -> Result<A, Error>,
+    wrap: impl Fn(A) -> Value,
+) -> Result<Value, Error> {
+    let mut acc = init;
+    for x in args {
+        match extract(x) {
+            None => return Ok(Value::Nil),
+            Some(v) => acc = combine(acc, v)?,
+        }
+    }
+    Ok(wrap(acc))
+}
+
+/// checked turns the result of checked arithmetic into an error when it overflowed.
+fn checked(x: Option<i64>) -> Result<i64, Error> {
+    x.ok_or_else(|| Error::msg("integer overflow"))
+}
+
+/// nested checks that a list or map being built isn't nested too deeply.
+fn nested(x: Value) -> Result<Value, Error> {
+    if x.deeper_than(MAX_VALUE_DEPTH) {
+        let message = format!(
+            "lists and maps are nested more than {} deep",
+            MAX_VALUE_DEPTH
+        );
+        return Err(Error::msg(message));
+    }
+    Ok(x)
+}
+
+fn add(args: &[Value]) -> Result<Value, Error> {
+    accumulate(args, int, 0, |x, y| checked(x.checked_add(y)), Value::Int)
+}
+
+fn mul(args: &[Value]) -> Result<Value, Error> {
+    accumulate(args, int, 1, |x, y| checked(x.checked_mul(y)), Value::Int)
+}
+
+fn sub(args: &[Value]) -> Result<Value, Error> {
+    accumulate(
+        args,
+        int,
+        None,
+        |x, y| match x {
+            None => Ok(Some(y)),
+            Some(acc) => Ok(Some(checked(acc.checked_sub(y))?)),
+        },
+        |x| Value::Int(x.unwrap_or(0)),
+    )
+}
+
+fn div(args: &[Value]) -> Result<Value, Error> {
+    accumulate(
+        args,
+        int,
+        None,
+        |x, y| match x {
+            None => Ok(Some(y)),
+            Some(_) if y == 0 => Err(Error::msg("division by zero")),
+            Some(acc) => Ok(Some(checked(acc.checked_div(y))?)),
+        },
+        |x| Value::Int(x.unwrap_or(1)),
+    )
+}
+
+fn eq(args: &[Value]) -> Result<Value, Error> {
+    accumulate(
+        args,
+        |x| match x {
+            Value::Nil => None,
+            x => Some(x.clone()),
+        },
+        (true, None),
+        |(equal, x), y| match x {
+            None => Ok((true, Some(y))),
+            Some(acc) => Ok((equal && acc == y, Some(y))),
+        },
+        |(equal, _)| bool_value(equal),
+    )
+}
+
+fn gt(args: &[Value]) -> Result<Value, Error> {
+    accumulate(
+        args,
+        int,
+        (true, None),
+        |(good, x), y| match x {
+            None => Ok((true, Some(y))),
+            Some(acc) => Ok((good && acc > y, Some(acc))),
+        },
+        |(good, _)| bool_value(good),
+    )
+}
+
+fn lt(args: &[Value]) -> Result<Value, Error> {
+    accumulate(
+        args,
+        int,
+        (true, None),
+        |(good, x), y| match x {
+            None => Ok((true, Some(y))),
+            Some(acc) => Ok((good && acc < y, Some(acc))),
+        },
+        |(good, _)| bool_value(good),
+    )
+}
+
+fn and(args: &[Value]) -> Result<Value, Error> {
+    Ok(bool_value(args.iter().all(Value::truthy)))
+}
+
+fn or(args: &[Value]) -> Result<Value, Error> {
+    Ok(bool_value(args.iter().any(Value::truthy)))
+}
+
+fn not(args: &[Value]) -> Result<Value, Error> {
+    Ok(bool_value(!args.first().is_some_and(Value::truthy)))
+}
+
+fn list(args: &[Value]) -> Result<Value, Error> {
+    nested(Value::List(args.to_vec()))
+}
+
+/// len counts the characters in a string, or the items in a list or a map.
+fn len(args: &[Value]) -> Result<Value, Error> {
+    let len = match &args[0] {
+        Value::Str(s) | Value::Markdown(s) => s.chars().count(),
+        Value::List(l) => l.len(),
+        Value::Map(m) => m.len(),
+        _ => return Ok(Value::Nil),
+    };
+    Ok(Value::Int(len as i64))
+}
+
+/// push adds a value at the end of a list, giving a new list.
+fn push(args: &[Value]) -> Result<Value, Error> {
+    match &args[0] {
+        Value::List(l) => {
+            let mut l = l.clone();
+            l.push(args[1].clone());
+            nested(Value::List(l))
+        }
+        _ => Ok(Value::Nil),
+    }
+}
+
+// The map functions return nil when given something other than a map, or a key that isn't a string.
+
+// `is_multiple_of` would need Rust 1.87.
+#[allow(clippy::manual_is_multiple_of)]
+fn dict(args: &[Value]) -> Result<Value, Error> {
+    if args.len() % 2 != 0 {
+        return Ok(Value::Nil);
+    }
+    let mut m = BTreeMap::new();
+    for pair in args.chunks(2) {
+        match string(&pair[0]) {
+            None => return Ok(Value::Nil),
+            Some(k) => m.insert(k.to_string(), pair[1].clone()),
+        };
+    }
+    nested(Value::Map(m))
+}
+
+/// get finds the value of a key in a map, or of an index in a list.
+fn get(args: &[Value]) -> Result<Value, Error> {
+    let found = match (&args[0], &args[1]) {
+        (Value::Map(m), Value::Str(k)) => m.get(k),
+        (Value::List(l), Value::Int(i)) => usize::try_from(*i).ok().and_then(|i| l.get(i)),
+        _ => None,
+    };
+    Ok(found.cloned().unwrap_or(Value::Nil))
+}
+
+fn put(args: &[Value]) -> Result<Value, Error> {
+    match (map(&args[0]), string(&args[1])) {
+        (Some(m), Some(k)) => {
+            let mut m = m.clone();
+            m.insert(k.to_string(), args[2].clone());
+            nested(Value::Map(m))
+        }
+        _ => Ok(Value::Nil),
+    }
+}
+
+fn has(args: &[Value]) -> Result<Value, Error> {
+    Ok(match (map(&args[0]), string(&args[1])) {
+        (Some(m), Some(k)) => bool_value(m.contains_key(k)),
+        _ => Value::Nil,
+    })
+}
+
+fn keys(args: &[Value]) -> Result<Value, Error> {
+    Ok(map(&args[0]).map_or(Value::Nil, |m| {
+        Value::List(m.keys().cloned().map(Value::Str).collect())
+    }))
+}
+
+fn values(args: &[Value]) -> Result<Value, Error> {
+    Ok(map(&args[0]).map_or(Value::Nil, |m| Value::List(m.values().cloned().collect())))
+}
+
+/// merge combines maps, with the keys of later maps replacing those of earlier ones.
+fn merge(args: &[Value]) -> Result<Value, Error> {
+    accumulate(
+        args,
+        map,
+        BTreeMap::new(),
+        |mut acc, m| {
+            acc.extend(m.iter().map(|(k, v)| (k.clone(), v.clone())));
+            Ok(acc)
+        },
+        Value::Map,
+    )
+}
+
+fn remove(args: &[Value]) -> Result<Value, Error> {
+    Ok(match (map(&args[0]), string(&args[1])) {
+        (Some(m), Some(k)) => {
+            let mut m = m.clone();
+            m.remove(k);
+            Value::Map(m)
+        }
+        _ => Value::Nil,
+    })
+}
+
+// The list functions calling other functions loop in Rust, so that they take a time
+// proportional to the length of the list, which loops in code can't do yet.
+
+/// fold combines the items of a list, one at a time, starting with `acc`, then
+/// `(f acc x)` for each item `x`.
+fn fold(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, Error> {
+    let xs = match items(&args[2]) {
+        None => return Ok(Value::Nil),
+        Some(xs) => xs,
+    };
+    let mut acc = args[1].clone();
+    for x in xs {
+        acc = interpreter.call_value(&args[0], vec![acc, x.clone()], span)?;
+    }
+    Ok(acc)
+}
+
+/// map_list calls a function on each item of a list, giving a list of the results.
+fn map_list(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, Error> {
+    let xs = match items(&args[1]) {
+        None => return Ok(Value::Nil),
+        Some(xs) => xs,
+    };
+    let mut ys = Vec::with_capacity(xs.len());
+    for x in xs {
+        ys.push(interpreter.call_value(&args[0], vec![x.clone()], span)?);
+    }
+    nested(Value::List(ys))
+}
+
+/// filter_list keeps the items of a list for which a function is true.
+fn filter_list(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, Error> {
+    let xs = match items(&args[1]) {
+        None => return Ok(Value::Nil),
+        Some(xs) => xs,
+    };
+    let mut kept = Vec::new();
+    for x in xs {
+        if interpreter
+            .call_value(&args[0], vec![x.clone()], span)?
+            .truthy()
+        {
+            kept.push(x.clone());
+        }
+    }
+    Ok(Value::List(kept))
+}
+
+/// register adds all of the builtin functions to an interpreter.
+pub fn register(interpreter: &mut Interpreter) {
+    interpreter.register_with_arity("+", Arity::Any, add);
+    interpreter.register_with_arity("*", Arity::Any, mul);
+    interpreter.register_with_arity("-", Arity::Any, sub);
+    interpreter.register_with_arity("/", Arity::Any, div);
+    interpreter.register_with_arity("=", Arity::Any, eq);
+    interpreter.register_with_arity(">", Arity::Any, gt);
+    interpreter.register_with_arity("<", Arity::Any, lt);
+    interpreter.register_with_arity("and", Arity::Any, and);
+    interpreter.register_with_arity("or", Arity::Any, or);
+    interpreter.register_with_arity("not", Arity::Exactly(1), not);
+    interpreter.register_with_arity("list", Arity::Any, list);
+    interpreter.register_with_arity("len", Arity::Exactly(1), len);
+    interpreter.register_with_arity("push", Arity::Exactly(2), push);
+    interpreter.register_with_arity("dict", Arity::Any, dict);
+    interpreter.register_with_arity("get", Arity::Exactly(2), get);
+    interpreter.register_with_arity("put", Arity::Exactly(3), put);
+    interpreter.register_with_arity("has?", Arity::Exactly(2), has);
+    interpreter.register_with_arity("keys", Arity::Exactly(1), keys);
+    interpreter.register_with_arity("values", Arity::Exactly(1), values);
+    interpreter.register_with_arity("merge", Arity::Any, merge);
+    interpreter.register_with_arity("remove", Arity::Exactly(2), remove);
+    interpreter.register_apply("fold", Arity::Exactly(3), fold);
+    interpreter.register_apply("map", Arity::Exactly(2), map_list);
+    interpreter.register_apply("filter", Arity::Exactly(2), filter_list);
+}
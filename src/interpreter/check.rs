@@ -0,0 +1,309 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::mem;
+use std::path::PathBuf;
+
+use crate::error::Error;
+use crate::interpreter::parser::{Definition, Expr, ExprKind, Ident, Statement};
+use crate::interpreter::resolve;
+use crate::interpreter::{cycle, definitions, included, parse_block, parse_definition, parse_expr};
+use crate::interpreter::{Arity, Interpreter};
+use crate::lexer::Lexer as DocLexer;
+use crate::parser::{DocumentChunk, Parser as DocParser};
+use crate::span::Span;
+
+/// Defined collects the names defined by a file.
+#[derive(Debug, Default)]
+struct Defined {
+    values: Vec<Ident>,
+    funcs: Vec<(Ident, usize)>,
+    exports: Option<Vec<Ident>>,
+}
+
+impl Defined {
+    /// qualify adds the names exported by a module to another set of names, after the module's name.
+    fn qualify(self, name: &Ident, into: &mut Defined) {
+        let exports = self.exports;
+        let exported = |i: &Ident| exports.as_ref().is_none_or(|e| e.contains(i));
+        let qualified = |i: Ident| Ident(format!("{}/{}", name.0, i.0));
+        into.values.extend(
+            self.values
+                .into_iter()
+                .filter(|i| exported(i))
+                .map(qualified),
+        );
+        into.funcs.extend(
+            self.funcs
+                .into_iter()
+                .filter(|(i, _)| exported(i))
+                .map(|(i, n)| (qualified(i), n)),
+        );
+    }
+}
+
+/// A Checker looks for problems in the code of a document.
+#[derive(Debug)]
+struct Checker<'a> {
+    /// The interpreter the document is going to run in, with the functions it already has.
+    interpreter: &'a Interpreter,
+    /// The values defined so far, at the top level of the document.
+    values: HashSet<Ident>,
+    /// The functions defined so far, along with how many arguments they take.
+    funcs: HashMap<Ident, usize>,
+    /// Every function defined in the document.
+    ///
+    /// Functions are looked up when they're called, so their bodies can use
+    /// functions which are defined further down.
+    all_funcs: HashMap<Ident, usize>,
+    /// The files being checked, with the innermost last.
+    files: Vec<PathBuf>,
+    problems: Vec<Error>,
+}
+
+impl Checker<'_> {
+    fn call(&mut self, funcs: &HashMap<Ident, usize>, ident: &Ident, arg_count: usize, span: Span) {
+        let arity = if ident.0 == "if" {
+            Arity::Between(2, 3)
+        } else if ident.0 == "include" {
+            Arity::Exactly(1)
+        } else {
+            match funcs.get(ident) {
+                Some(&n) => Arity::Exactly(n),
+                None => match self.interpreter.arity(ident) {
+                    Some(arity) => arity,
+                    None => {
+                        let message = format!("unknown function `{}`", ident.0);
+                        self.problems.push(Error::new(message, span));
+                        return;
+                    }
+                },
+            }
+        };
+        if !arity.accepts(arg_count) {
+            let message = format!(
+                "`{}` takes {}, but was given {}",
+                ident.0,
+                arity.describe(),
+                arg_count
+            );
+            self.problems.push(Error::new(message, span));
+        }
+    }
+
+    /// expr checks an expression, which can use some values and functions.
+    fn expr(&mut self, values: &HashSet<Ident>, funcs: &HashMap<Ident, usize>, expr: &Expr) {
+        match &expr.item {
+            ExprKind::Nil | ExprKind::Int(_) | ExprKind::Str(_) => {}
+            ExprKind::Ident(i) => {
+                // Functions can be used as values too.
+                let known = values.contains(i)
+                    || funcs.contains_key(i)
+                    || self.interpreter.arity(i).is_some();
+                if !known {
+                    let message = format!("unknown value `{}`", i.0);
+                    self.problems.push(Error::new(message, expr.span));
+                }
+            }
+            ExprKind::Call { name: i, args } => {
+                if let Some(path) = included(expr) {
+                    self.read(path, expr.span);
+                }
+                // Values could hold any function, so only calls to functions by their names get checked.
+                if !values.contains(&i.item) {
+                    self.call(funcs, &i.item, args.len(), expr.span);
+                }
+                for arg in args {
+                    self.expr(values, funcs, arg);
+                }
+            }
+        }
+    }
+
+    fn top_level_expr(&mut self, expr: &Expr) {
+        if let Some(path) = included(expr) {
+            self.include(path, expr.span);
+            return;
+        }
+        let values = mem::take(&mut self.values);
+        let funcs = mem::take(&mut self.funcs);
+        self.expr(&values, &funcs, expr);
+        self.values = values;
+        self.funcs = funcs;
+    }
+
+    /// read reads a file used by the file we're checking.
+    fn read(&mut self, path: &str, span: Span) -> Option<(PathBuf, String)> {
+        let file = resolve(self.files.last().map(PathBuf::as_path), path);
+        let read = fs::canonicalize(file).and_then(|f| Ok((f.clone(), fs::read_to_string(f)?)));
+        match read {
+            Err(e) => {
+                let message = format!("failed to read `{}`: {}", path, e);
+                self.problems.push(Error::new(message, span));
+                None
+            }
+            Ok(read) => Some(read),
+        }
+    }
+
+    /// collect finds what another file defines, without checking that file.
+    ///
+    /// Included files run their own includes, so `includes` follows them too.
+    fn collect(&mut self, path: &str, span: Span, defined: &mut Defined, includes: bool) {
+        let (file, src) = match self.read(path, span) {
+            None => return,
+            Some(read) => read,
+        };
+        if let Some(e) = cycle(&self.files, &file, span) {
+            self.problems.push(e);
+            return;
+        }
+        self.files.push(file);
+        for chunk in DocParser::new(DocLexer::new(&src)) {
+            if let (true, DocumentChunk::Interpolate(code)) = (includes, &chunk.item) {
+                if let Some(path) = parse_expr(code).ok().as_ref().and_then(included) {
+                    self.collect(path, span, defined, true);
+                }
+            }
+            for def in definitions(&chunk.item).unwrap_or_default() {
+                match def {
+                    Definition::Value { name: i, .. } => defined.values.push(i.item),
+                    Definition::Func { name: i, args, .. } => {
+                        defined.funcs.push((i.item, args.len()))
+                    }
+                    Definition::Import {
+                        path, alias: None, ..
+                    } => self.collect(&path, span, defined, false),
+                    Definition::Import {
+                        path,
+                        alias: Some(name),
+                        ..
+                    } => {
+                        let mut module = Defined::default();
+                        self.collect(&path, span, &mut module, false);
+                        module.qualify(&name.item, defined);
+                    }
+                    Definition::Export { names } => {
+                        let names = names.into_iter().map(|n| n.item);
+                        defined.exports.get_or_insert_with(Vec::new).extend(names)
+                    }
+                }
+            }
+        }
+        self.files.pop();
+    }
+
+    /// import defines what another file defines, with a name in front of it for modules.
+    fn import(&mut self, path: &str, span: Span, name: Option<&Ident>) {
+        let mut defined = Defined::default();
+        match name {
+            None => self.collect(path, span, &mut defined, false),
+            Some(name) => {
+                let mut module = Defined::default();
+                self.collect(path, span, &mut module, false);
+                module.qualify(name, &mut defined);
+            }
+        }
+        self.define(defined);
+    }
+
+    /// include defines what an included file defines, since it runs in the same scope.
+    fn include(&mut self, path: &str, span: Span) {
+        let mut defined = Defined::default();
+        self.collect(path, span, &mut defined, true);
+        self.define(defined);
+    }
+
+    /// define adds the names defined by another file.
+    fn define(&mut self, defined: Defined) {
+        self.values.extend(defined.values);
+        for (i, n) in defined.funcs {
+            self.funcs.insert(i.clone(), n);
+            self.all_funcs.insert(i, n);
+        }
+    }
+
+    fn definition(&mut self, def: &Definition) {
+        match def {
+            Definition::Value { name: i, body: e } => {
+                self.top_level_expr(e);
+                self.values.insert(i.item.clone());
+            }
+            Definition::Func {
+                name: i,
+                args,
+                body: e,
+            } => {
+                self.funcs.insert(i.item.clone(), args.len());
+                // Function bodies only see their own arguments.
+                let values = args.iter().map(|a| a.item.clone()).collect();
+                let funcs = mem::take(&mut self.all_funcs);
+                self.expr(&values, &funcs, e);
+                self.all_funcs = funcs;
+            }
+            Definition::Import {
+                path,
+                path_span: span,
+                alias: name,
+            } => self.import(path, *span, name.as_ref().map(|n| &n.item)),
+            Definition::Export { .. } => {}
+        }
+    }
+}
+
+/// check looks for problems in the chunks of a document, without running any code.
+///
+/// This finds code that fails to parse, values and functions that aren't defined
+/// at the point where they're used, and calls with the wrong number of arguments.
+/// Values and functions the interpreter already has, like builtins, count as defined,
+/// along with those defined by imported and included files.
+pub fn check<'a>(
+    interpreter: &Interpreter,
+    chunks: impl IntoIterator<Item = &'a DocumentChunk>,
+) -> Vec<Error> {
+    let mut parsed = Vec::new();
+    let mut problems = Vec::new();
+    for chunk in chunks {
+        let result = match chunk {
+            DocumentChunk::Raw(_) => continue,
+            DocumentChunk::Comment(c) => {
+                parse_definition(c).map(|d| vec![Statement::Definition(d)])
+            }
+            DocumentChunk::Interpolate(c) => parse_expr(c).map(|e| vec![Statement::Expr(e)]),
+            DocumentChunk::Block { code, .. } => parse_block(code),
+        };
+        match result {
+            Err(e) => problems.push(e),
+            Ok(statements) => parsed.extend(statements),
+        }
+    }
+
+    let all_funcs = parsed
+        .iter()
+        .filter_map(|code| match code {
+            Statement::Definition(Definition::Func { name: i, args, .. }) => {
+                Some((i.item.clone(), args.len()))
+            }
+            _ => None,
+        })
+        .collect();
+    let mut checker = Checker {
+        interpreter,
+        values: interpreter.value_names().cloned().collect(),
+        funcs: HashMap::new(),
+        all_funcs,
+        files: interpreter
+            .current_file()
+            .map(PathBuf::from)
+            .into_iter()
+            .collect(),
+        problems,
+    };
+    for code in &parsed {
+        match code {
+            Statement::Definition(def) => checker.definition(def),
+            Statement::Expr(expr) => checker.top_level_expr(expr),
+        }
+    }
+    checker.problems.sort_by_key(|p| p.span.start);
+    checker.problems
+}
@@ -1,7 +1,13 @@
 use std::{iter::Peekable, str::Chars};
 
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::span::{Span, Spanned};
+
 /// Represents a Token produced by our lexer.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value")]
 pub enum Token {
     /// The `is` keyword
     Is,
@@ -15,81 +21,199 @@ pub enum Token {
     Identifier(String),
     // A signed integer
     Int(i64),
+    /// A string literal, with its escapes already replaced
+    Str(String),
+    /// A comment, which doesn't affect the meaning of the code
+    Comment(Comment),
 }
 
+/// A Comment is either a `;` comment, running until the end of the line, or a `#| |#` block.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Comment {
+    /// The text of the comment, including the characters marking it as a comment.
+    pub text: String,
+    /// Whether or not this comment follows other code on the same line.
+    pub trailing: bool,
+}
+
+/// The symbols that can appear in identifiers, along with letters.
+const IDENT_SYMBOLS: &str = "_+-/*<=>?";
+
 /// A lexer produces tokens as an iterator.
 #[derive(Clone, Debug)]
 pub struct Lexer<'a> {
     chars: Peekable<Chars<'a>>,
+    /// The current position in the document containing this code.
+    pos: usize,
+    /// Whether or not we've seen a newline since the last token.
+    newline: bool,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(src: &'a str) -> Self {
+        Self::at(src, 0)
+    }
+
+    /// Create a lexer for code starting at a given position in its document.
+    pub fn at(src: &'a str, offset: usize) -> Self {
         Self {
             chars: src.chars().peekable(),
+            pos: offset,
+            newline: true,
         }
     }
 
-    fn continue_int_lit(&mut self, start: char) -> i64 {
-        let mut acc: i64 = start.to_digit(10).unwrap() as i64;
-        while let Some(&peek) = self.chars.peek() {
-            match peek.to_digit(10) {
-                None => break,
-                Some(d) => {
-                    self.chars.next();
-                    acc = 10 * acc + d as i64
+    /// pos returns the current position in the document containing this code.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// bump_if advances to the next character, if it matches a predicate.
+    fn bump_if(&mut self, pred: impl FnOnce(&char) -> bool) -> Option<char> {
+        let c = self.chars.next_if(pred)?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    /// continue_int_lit reads an integer literal, which is None if it doesn't fit in an i64.
+    fn continue_int_lit(&mut self, start: char) -> Option<i64> {
+        let mut acc = Some(start.to_digit(10).unwrap() as i64);
+        while let Some(c) = self.bump_if(|c| c.is_ascii_digit()) {
+            acc = acc
+                .and_then(|acc| acc.checked_mul(10))
+                .and_then(|acc| acc.checked_add(c.to_digit(10).unwrap() as i64));
+        }
+        acc
+    }
+
+    /// continue_string reads a string literal, after its opening `"`.
+    fn continue_string(&mut self, start: usize) -> Result<String, Error> {
+        let mut string = String::new();
+        loop {
+            let escape_start = self.pos;
+            match self.bump_if(|_| true) {
+                None => {
+                    return Err(Error::new(
+                        "unterminated string".to_string(),
+                        Span::new(start, self.pos),
+                    ))
                 }
+                Some('"') => return Ok(string),
+                Some('\\') => match self.bump_if(|_| true) {
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    c => {
+                        let escape = c.map_or(String::new(), String::from);
+                        return Err(Error::new(
+                            format!("unknown escape: `\\{}`", escape),
+                            Span::new(escape_start, self.pos),
+                        ));
+                    }
+                },
+                Some(c) => string.push(c),
             }
         }
-        acc
     }
 
     fn continue_identifier(&mut self, start: char) -> String {
         let mut ident = String::from(start);
-        while let Some(&peek) = self.chars.peek() {
-            if !(peek.is_alphanumeric() || peek == '_') {
-                break;
-            }
-            self.chars.next();
-            ident.push(peek);
+        while let Some(c) = self.bump_if(|&c| c.is_alphanumeric() || IDENT_SYMBOLS.contains(c)) {
+            ident.push(c);
         }
         ident
     }
 
+    fn continue_line_comment(&mut self) -> String {
+        let mut text = String::from(";");
+        while let Some(c) = self.bump_if(|&c| c != '\n') {
+            text.push(c);
+        }
+        text.trim_end().to_string()
+    }
+
+    /// continue_block_comment reads a block comment, after its opening `#`.
+    fn continue_block_comment(&mut self) -> Option<String> {
+        let mut text = String::from("#");
+        while let Some(c) = self.bump_if(|_| true) {
+            text.push(c);
+            if text.len() >= 4 && text.ends_with("|#") {
+                return Some(text);
+            }
+        }
+        None
+    }
+
     fn skip_whitespace(&mut self) {
-        // UNSTABLE: you could use `next_if` here when it stabilizes
-        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+        while let Some(c) = self.bump_if(|c| c.is_whitespace()) {
+            self.newline |= c == '\n';
+        }
     }
 }
 
 impl<'a> Iterator for Lexer<'a> {
-    type Item = Result<Token, String>;
+    type Item = Result<Spanned<Token>, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.skip_whitespace();
 
-        let next = match self.chars.next() {
-            None => return None,
-            Some(c) => c,
-        };
+        let start = self.pos;
+        let next = self.bump_if(|_| true)?;
 
-        match next {
-            '(' => return Some(Ok(Token::OpenParens)),
-            ')' => return Some(Ok(Token::CloseParens)),
-            c if c.is_digit(10) => {
-                let lit = self.continue_int_lit(c);
-                return Some(Ok(Token::Int(lit)));
-            }
-            c if c.is_alphabetic() || "_+-/*<=>".contains(c) => {
+        let tok = match next {
+            ';' => Token::Comment(Comment {
+                text: self.continue_line_comment(),
+                trailing: !self.newline,
+            }),
+            '#' if self.chars.peek() == Some(&'|') => match self.continue_block_comment() {
+                None => {
+                    return Some(Err(Error::new(
+                        "unterminated block comment".to_string(),
+                        Span::new(start, self.pos),
+                    )))
+                }
+                Some(text) => Token::Comment(Comment {
+                    text,
+                    trailing: !self.newline,
+                }),
+            },
+            '(' => Token::OpenParens,
+            ')' => Token::CloseParens,
+            '"' => match self.continue_string(start) {
+                Err(e) => return Some(Err(e)),
+                Ok(string) => Token::Str(string),
+            },
+            c if c.is_ascii_digit() => match self.continue_int_lit(c) {
+                None => {
+                    return Some(Err(Error::new(
+                        "integer literal too large".to_string(),
+                        Span::new(start, self.pos),
+                    )))
+                }
+                Some(i) => Token::Int(i),
+            },
+            c if c.is_alphabetic() || IDENT_SYMBOLS.contains(c) => {
                 let ident = self.continue_identifier(c);
-                let tok = match ident.as_str() {
+                match ident.as_str() {
                     "is" => Token::Is,
                     "nil" => Token::Nil,
                     _ => Token::Identifier(ident),
-                };
-                return Some(Ok(tok));
+                }
             }
-            c => return Some(Err(format!("unexpected character: `{}`", c))),
-        }
+            c => {
+                return Some(Err(Error::new(
+                    format!("unexpected character: `{}`", c),
+                    Span::new(start, self.pos),
+                )))
+            }
+        };
+        self.newline = false;
+        // Line comments end with their text, without the whitespace after it.
+        let end = match &tok {
+            Token::Comment(c) if c.text.starts_with(';') => start + c.text.len(),
+            _ => self.pos,
+        };
+        Some(Ok(Spanned::new(tok, Span::new(start, end))))
     }
 }
@@ -0,0 +1,285 @@
+use crate::error::Error;
+use crate::interpreter::builtins::{int, items, string};
+use crate::interpreter::{Arity, Interpreter, Value};
+
+// These functions make Markdown from values, escaping them so that they stay text
+// wherever they end up, and can't break the document around them.
+
+/// Where a value gets rendered, which changes what needs escaping in it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Context {
+    /// Text in a paragraph, or in another block like a heading or a list item.
+    Paragraph,
+    /// A cell of a table, which can't span lines, and ends at a pipe.
+    Cell,
+}
+
+/// text gives the text of a value, with nil being empty.
+fn text(x: &Value) -> String {
+    match x {
+        Value::Nil => String::new(),
+        Value::Str(s) | Value::Markdown(s) => s.clone(),
+        x => x.to_string(),
+    }
+}
+
+/// one_line joins the lines of some text with spaces, since most Markdown can't span lines.
+fn one_line(s: &str) -> String {
+    s.lines().map(str::trim).collect::<Vec<_>>().join(" ")
+}
+
+/// escape_line escapes the characters of a line which Markdown would give a meaning to.
+///
+/// Some characters only mean something at the start of a line, like list markers,
+/// which get escaped there. Pipes are only escaped in table cells.
+fn escape_line(line: &str, context: Context) -> String {
+    let mut out = String::new();
+    for c in line.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '~' | '&'
+        ) || (c == '|' && context == Context::Cell)
+        {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    let start = out.len() - out.trim_start().len();
+    let rest = &out[start..];
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let marker = if rest.starts_with(['-', '+']) {
+        Some(0)
+    } else if digits > 0 && rest[digits..].starts_with(['.', ')']) {
+        Some(digits)
+    } else {
+        None
+    };
+    let after = marker.map(|m| &rest[m + 1..]);
+    // Lines of only dashes or equal signs would underline the line before them.
+    let underline = !rest.is_empty()
+        && (rest.trim_end().chars().all(|c| c == '-') || rest.trim_end().chars().all(|c| c == '='));
+    if underline {
+        out.insert(start, '\\');
+    } else if let (Some(m), Some(after)) = (marker, after) {
+        if after.is_empty() || after.starts_with([' ', '\t']) {
+            out.insert(start + m, '\\');
+        }
+    }
+    out
+}
+
+/// escape escapes the characters of some text which Markdown would give a meaning to.
+///
+/// The text is also put on one line, without the spaces around it.
+fn escape(s: &str) -> String {
+    escape_line(&one_line(s), Context::Paragraph)
+}
+
+/// inline gives a value as Markdown on one line, escaping it unless it's Markdown already.
+fn inline(x: &Value) -> String {
+    match x {
+        Value::Markdown(s) => one_line(s),
+        x => escape(&text(x)),
+    }
+}
+
+/// render gives a value as Markdown, escaping it for the context it ends up in, unless
+/// it's Markdown already.
+///
+/// In paragraphs, the lines of the value are kept, so that it can make several of them.
+pub(crate) fn render(x: &Value, context: Context) -> String {
+    match (x, context) {
+        (Value::Markdown(s), Context::Paragraph) => s.clone(),
+        (Value::Markdown(s), Context::Cell) => one_line(s).replace('|', "\\|"),
+        (x, Context::Paragraph) => {
+            let lines: Vec<_> = x
+                .to_string()
+                .split('\n')
+                .map(|l| escape_line(l, context))
+                .collect();
+            lines.join("\n")
+        }
+        (x, Context::Cell) => escape_line(one_line(&x.to_string()).trim(), context),
+    }
+}
+
+/// destination formats the destination of a link, putting it between `<` and `>` if needed.
+fn destination(url: &str) -> String {
+    let url = one_line(url);
+    let mut out = String::new();
+    for c in url.chars() {
+        if matches!(c, '\\' | '<' | '>') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    if url.is_empty() || url.contains([' ', '(', ')', '<', '>']) {
+        format!("<{}>", out)
+    } else {
+        out
+    }
+}
+
+/// list_lines makes the lines of a list, where lists inside of it are nested under
+/// the item before them.
+fn list_lines(list: &[Value], numbered: bool, indent: usize, lines: &mut Vec<String>) {
+    let mut number = 0;
+    let mut width = if numbered { 3 } else { 2 };
+    for item in list {
+        if let Value::List(nested) = item {
+            list_lines(nested, numbered, indent + width, lines);
+            continue;
+        }
+        number += 1;
+        let marker = if numbered {
+            format!("{}.", number)
+        } else {
+            "-".to_string()
+        };
+        width = marker.len() + 1;
+        let line = format!("{}{} {}", " ".repeat(indent), marker, inline(item));
+        lines.push(line.trim_end().to_string());
+    }
+}
+
+fn heading(args: &[Value]) -> Result<Value, Error> {
+    let level = match int(&args[0]) {
+        None => return Ok(Value::Nil),
+        Some(level) => level,
+    };
+    if !(1..=6).contains(&level) {
+        let message = format!("headings have levels from 1 to 6, not {}", level);
+        return Err(Error::msg(message));
+    }
+    let hashes = "#".repeat(level as usize);
+    Ok(Value::Markdown(format!("{} {}", hashes, inline(&args[1]))))
+}
+
+fn list(args: &[Value], numbered: bool) -> Result<Value, Error> {
+    Ok(items(&args[0]).map_or(Value::Nil, |list| {
+        let mut lines = Vec::new();
+        list_lines(list, numbered, 0, &mut lines);
+        Value::Markdown(lines.join("\n"))
+    }))
+}
+
+fn bullets(args: &[Value]) -> Result<Value, Error> {
+    list(args, false)
+}
+
+fn numbered(args: &[Value]) -> Result<Value, Error> {
+    list(args, true)
+}
+
+fn link(args: &[Value]) -> Result<Value, Error> {
+    let (text, url) = (inline(&args[0]), destination(&text(&args[1])));
+    Ok(Value::Markdown(format!("[{}]({})", text, url)))
+}
+
+fn image(args: &[Value]) -> Result<Value, Error> {
+    let (alt, src) = (inline(&args[0]), destination(&text(&args[1])));
+    Ok(Value::Markdown(format!("![{}]({})", alt, src)))
+}
+
+fn bold(args: &[Value]) -> Result<Value, Error> {
+    let text = inline(&args[0]);
+    if text.is_empty() {
+        return Ok(Value::Markdown(text));
+    }
+    Ok(Value::Markdown(format!("**{}**", text)))
+}
+
+/// code makes a code span, delimited by more ticks than any run of ticks inside of it.
+fn code(args: &[Value]) -> Result<Value, Error> {
+    let code = one_line(&text(&args[0]));
+    if code.is_empty() {
+        return Ok(Value::Markdown(code));
+    }
+    let longest = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let ticks = "`".repeat(longest + 1);
+    // Spaces on both sides get removed, so code starting or ending with ticks, or
+    // with spaces on both sides, gets one more.
+    let padded = code.starts_with('`')
+        || code.ends_with('`')
+        || (code.starts_with(' ') && code.ends_with(' '));
+    let pad = if padded { " " } else { "" };
+    let span = format!("{}{}{}{}{}", ticks, pad, code, pad, ticks);
+    Ok(Value::Markdown(span))
+}
+
+/// cell formats a value as the cell of a table.
+fn cell(x: &Value) -> String {
+    match x {
+        Value::Nil => String::new(),
+        x => render(x, Context::Cell),
+    }
+}
+
+/// table makes a Markdown table, from a list of headers and a list of rows.
+///
+/// Rows are lists of cells, or maps from headers to cells. An optional list of
+/// alignments, like `"left"`, `"center"`, or `"right"`, aligns the columns.
+fn table(args: &[Value]) -> Result<Value, Error> {
+    let (headers, rows) = match (items(&args[0]), items(&args[1])) {
+        (Some(headers), Some(rows)) if !headers.is_empty() => (headers, rows),
+        _ => return Ok(Value::Nil),
+    };
+    let aligns = match args.get(2).map(items) {
+        None => Vec::new(),
+        Some(None) => return Ok(Value::Nil),
+        Some(Some(aligns)) => aligns.to_vec(),
+    };
+    let delimiters = (0..headers.len())
+        .map(|i| match aligns.get(i) {
+            None | Some(Value::Nil) => Ok("---"),
+            Some(Value::Str(a)) if a == "left" => Ok(":---"),
+            Some(Value::Str(a)) if a == "center" => Ok(":---:"),
+            Some(Value::Str(a)) if a == "right" => Ok("---:"),
+            Some(a) => Err(Error::msg(format!(
+                "unknown alignment `{}`, expected \"left\", \"center\", or \"right\"",
+                a
+            ))),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let row = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+    let mut lines = vec![
+        row(headers.iter().map(cell).collect()),
+        row(delimiters.iter().map(|d| d.to_string()).collect()),
+    ];
+    for r in rows {
+        let cells = match r {
+            Value::List(l) => (0..headers.len())
+                .map(|i| l.get(i).map_or(String::new(), cell))
+                .collect(),
+            Value::Map(m) => headers
+                .iter()
+                .map(|h| {
+                    let found = string(h).and_then(|h| m.get(h));
+                    found.map_or(String::new(), cell)
+                })
+                .collect(),
+            _ => return Ok(Value::Nil),
+        };
+        lines.push(row(cells));
+    }
+    Ok(Value::Markdown(lines.join("\n")))
+}
+
+/// raw marks a value as Markdown, so that it doesn't get escaped.
+fn raw(args: &[Value]) -> Result<Value, Error> {
+    Ok(Value::Markdown(args[0].to_string()))
+}
+
+/// register adds the functions making Markdown to an interpreter.
+pub fn register(interpreter: &mut Interpreter) {
+    interpreter.register_with_arity("heading", Arity::Exactly(2), heading);
+    interpreter.register_with_arity("bullets", Arity::Exactly(1), bullets);
+    interpreter.register_with_arity("numbered", Arity::Exactly(1), numbered);
+    interpreter.register_with_arity("link", Arity::Exactly(2), link);
+    interpreter.register_with_arity("image", Arity::Exactly(2), image);
+    interpreter.register_with_arity("bold", Arity::Exactly(1), bold);
+    interpreter.register_with_arity("code", Arity::Exactly(1), code);
+    interpreter.register_with_arity("table", Arity::Between(2, 3), table);
+    interpreter.register_with_arity("raw", Arity::Exactly(1), raw);
+}
@@ -1,46 +1,376 @@
+mod builtins;
+mod check;
 mod lexer;
+pub(crate) mod markdown;
 mod parser;
+pub mod pretty;
 
-use std::{collections::HashMap, fmt, mem};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{fmt, mem, str::FromStr, sync::Arc};
 
-use parser::{Definition, Expr};
+pub use check::check;
+pub use lexer::Comment;
+pub use parser::{Definition, Expr, ExprKind, ParseResult, Statement};
 
-use crate::parser::Code;
+use crate::error::{Error, Errors};
+use crate::lexer::Lexer as DocLexer;
+use crate::parser::{Code, DocumentChunk, Parser as DocParser};
+use crate::span::{Span, Spanned};
 
 use self::parser::Ident;
 
+/// A Value is the result of evaluating some code.
+///
+/// More kinds of values may be added, so matching on them needs a catch-all arm.
 #[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum Value {
     Int(i64),
+    Str(String),
+    /// Markdown which is already formatted, like the one made by `bold` or `raw`, and
+    /// doesn't get escaped when rendered.
+    Markdown(String),
+    List(Vec<Value>),
+    /// A map from strings to values, ordered by key.
+    Map(BTreeMap<String, Value>),
+    /// A function, which can be passed to other functions.
+    Func(FuncRef),
     Nil,
 }
 
 impl Value {
-    fn truthy(&self) -> bool {
+    /// truthy tells whether a value counts as true in conditions: anything but 0,
+    /// empty strings, lists and maps, and nil.
+    pub fn truthy(&self) -> bool {
         match self {
             Value::Int(x) => *x != 0,
+            Value::Str(s) | Value::Markdown(s) => !s.is_empty(),
+            Value::List(l) => !l.is_empty(),
+            Value::Map(m) => !m.is_empty(),
+            Value::Func(_) => true,
             Value::Nil => false,
         }
     }
+
+    /// deeper_than tells whether lists and maps are nested more than `depth` levels
+    /// deep in this value, without looking any deeper than that.
+    pub(crate) fn deeper_than(&self, depth: usize) -> bool {
+        let mut items: Box<dyn Iterator<Item = &Value>> = match self {
+            Value::List(l) => Box::new(l.iter()),
+            Value::Map(m) => Box::new(m.values()),
+            _ => return false,
+        };
+        depth == 0 || items.any(|v| v.deeper_than(depth - 1))
+    }
 }
 
+/// Lists and maps display as the code building them, so the strings inside of them are quoted.
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nested = |v: &Value| match v {
+            Value::Str(s) => pretty::quote(s),
+            Value::Markdown(s) => format!("(raw {})", pretty::quote(s)),
+            v => v.to_string(),
+        };
         match self {
             Value::Int(i) => write!(f, "{}", i),
+            Value::Str(s) | Value::Markdown(s) => write!(f, "{}", s),
+            Value::List(l) => {
+                write!(f, "(list")?;
+                for v in l {
+                    write!(f, " {}", nested(v))?;
+                }
+                write!(f, ")")
+            }
+            Value::Map(m) => {
+                write!(f, "(dict")?;
+                for (k, v) in m {
+                    write!(f, " {} {}", pretty::quote(k), nested(v))?;
+                }
+                write!(f, ")")
+            }
+            Value::Func(func) => write!(f, "{}", func.name),
             Value::Nil => write!(f, "nil"),
         }
     }
 }
 
-#[derive(Clone, Debug)]
-struct Function {
-    args: Vec<Ident>,
-    body: Expr,
+/// Arity describes how many arguments a function accepts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Arity {
+    /// Exactly this many arguments.
+    Exactly(usize),
+    /// Between these two numbers of arguments, inclusively.
+    Between(usize, usize),
+    /// Any number of arguments.
+    Any,
+}
+
+impl Arity {
+    pub(crate) fn accepts(self, count: usize) -> bool {
+        match self {
+            Arity::Exactly(n) => count == n,
+            Arity::Between(lo, hi) => lo <= count && count <= hi,
+            Arity::Any => true,
+        }
+    }
+
+    pub(crate) fn describe(self) -> String {
+        let plural = |n| if n == 1 { "" } else { "s" };
+        match self {
+            Arity::Exactly(n) => format!("{} argument{}", n, plural(n)),
+            Arity::Between(lo, hi) => format!("{} to {} arguments", lo, hi),
+            Arity::Any => "any number of arguments".to_string(),
+        }
+    }
+}
+
+/// A NativeFn is a function written in Rust, which can be called from code.
+pub type NativeFn = Arc<dyn Fn(&[Value]) -> Result<Value, Error> + Send + Sync>;
+
+/// An ApplyFn is a builtin which calls the functions it's given, using the interpreter.
+///
+/// It gets the span of the call, for the errors of the functions it calls.
+pub(crate) type ApplyFn = fn(&mut Interpreter, &[Value], Span) -> Result<Value, Error>;
+
+/// A FuncRef refers to a function, by the name it had where it was used as a value.
+#[derive(Clone)]
+pub struct FuncRef {
+    name: String,
+    func: Arc<Function>,
+}
+
+impl FuncRef {
+    /// name is the name of the function.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Debug for FuncRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("FuncRef").field(&self.name).finish()
+    }
+}
+
+/// Functions are only equal to themselves, whatever name they're used with.
+impl PartialEq for FuncRef {
+    fn eq(&self, other: &Self) -> bool {
+        self.func.same(&other.func)
+    }
+}
+
+#[derive(Clone)]
+enum Function {
+    /// A function defined in code, inside of a module or not.
+    ///
+    /// The functions its body calls are looked up in that module. Without one, they're
+    /// looked up where the function itself is: in the module holding it, or at the top level.
+    Defined {
+        args: Vec<Ident>,
+        /// The body, shared between the copies of the function, which tells them apart
+        /// from other functions.
+        body: Arc<Expr>,
+        module: Option<Arc<Module>>,
+        /// The file imported without a name that defined it, if any.
+        imported: Option<PathBuf>,
+    },
+    /// A function registered by the host, or a builtin.
+    Native { arity: Arity, func: NativeFn },
+    /// A builtin calling other functions, like `map`.
+    Apply { arity: Arity, func: ApplyFn },
+}
+
+impl Function {
+    /// same tells whether two functions are copies of the same one.
+    fn same(&self, other: &Function) -> bool {
+        match (self, other) {
+            (Function::Defined { body: a, .. }, Function::Defined { body: b, .. }) => {
+                Arc::ptr_eq(a, b)
+            }
+            (Function::Native { func: a, .. }, Function::Native { func: b, .. }) => {
+                Arc::ptr_eq(a, b)
+            }
+            (Function::Apply { func: a, .. }, Function::Apply { func: b, .. }) => {
+                *a as usize == *b as usize
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Function::Defined {
+                args,
+                body,
+                module,
+                imported,
+            } => f
+                .debug_struct("Defined")
+                .field("args", args)
+                .field("body", body)
+                .field("module", &module.as_ref().map(|m| &m.file))
+                .field("imported", imported)
+                .finish(),
+            Function::Native { arity, .. } => {
+                f.debug_struct("Native").field("arity", arity).finish()
+            }
+            Function::Apply { arity, .. } => f.debug_struct("Apply").field("arity", arity).finish(),
+        }
+    }
 }
 
 fn new_parser<'a>(code: &'a Code) -> parser::Parser<'a> {
-    parser::Parser::new(lexer::Lexer::new(&code.0))
+    parser::Parser::new(lexer::Lexer::at(&code.src, code.offset))
+}
+
+/// parse_definition parses some code as a definition, without running it.
+pub fn parse_definition(code: &Code) -> ParseResult<Definition> {
+    new_parser(code).top_level_definition()
+}
+
+/// parse_expr parses some code as an expression, without running it.
+pub fn parse_expr(code: &Code) -> ParseResult<Expr> {
+    new_parser(code).top_level_expr()
+}
+
+/// parse_block parses a block of code, made of definitions and expressions, without running it.
+pub fn parse_block(code: &Code) -> ParseResult<Vec<Statement>> {
+    new_parser(code).top_level_block()
+}
+
+/// definitions parses the definitions in a chunk of a document, ignoring its expressions.
+pub(crate) fn definitions(chunk: &DocumentChunk) -> ParseResult<Vec<Definition>> {
+    match chunk {
+        DocumentChunk::Comment(c) => Ok(vec![parse_definition(c)?]),
+        DocumentChunk::Block { code, .. } => Ok(parse_block(code)?
+            .into_iter()
+            .filter_map(|s| match s {
+                Statement::Definition(def) => Some(def),
+                Statement::Expr(_) => None,
+            })
+            .collect()),
+        DocumentChunk::Interpolate(_) | DocumentChunk::Raw(_) => Ok(Vec::new()),
+    }
+}
+
+/// comments finds the comments in some code, which parsing ignores.
+pub fn comments(code: &Code) -> Vec<Spanned<Comment>> {
+    lexer::Lexer::at(&code.src, code.offset)
+        .flatten()
+        .filter_map(|tok| match tok.item {
+            lexer::Token::Comment(c) => Some(Spanned::new(c, tok.span)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// incomplete tells whether some code is waiting for more, because it has parentheses
+/// still waiting to be closed, or it ends with `is`, like the first line of a definition.
+///
+/// Code that fails to lex has nothing left to wait for, so this returns false.
+pub fn incomplete(code: &Code) -> bool {
+    let mut last = None;
+    for tok in lexer::Lexer::new(&code.src) {
+        match tok {
+            Err(_) => return false,
+            Ok(Spanned {
+                item: lexer::Token::Comment(_),
+                ..
+            }) => {}
+            Ok(tok) => last = Some(tok.item),
+        }
+    }
+    last == Some(lexer::Token::Is) || unclosed_parens(code) > 0
+}
+
+/// unclosed_parens counts how many parentheses in some code are still waiting to be closed.
+///
+/// Code that fails to lex has nothing left to wait for, so this returns 0.
+pub fn unclosed_parens(code: &Code) -> i64 {
+    let mut unclosed = 0;
+    for tok in lexer::Lexer::new(&code.src) {
+        match tok {
+            Err(_) => return 0,
+            Ok(tok) => match tok.item {
+                lexer::Token::OpenParens => unclosed += 1,
+                lexer::Token::CloseParens => unclosed -= 1,
+                _ => {}
+            },
+        }
+    }
+    unclosed
+}
+
+/// resolve finds the file at a path, relative to the directory of another file if there's one.
+pub(crate) fn resolve(base: Option<&Path>, path: &str) -> PathBuf {
+    match base.and_then(Path::parent) {
+        None => PathBuf::from(path),
+        Some(dir) => dir.join(path),
+    }
+}
+
+/// cycle makes an error if a file is already among the files being run.
+pub(crate) fn cycle(files: &[PathBuf], file: &Path, span: Span) -> Option<Error> {
+    let i = files.iter().position(|f| f == file)?;
+    let cycle: Vec<_> = files[i..]
+        .iter()
+        .map(PathBuf::as_path)
+        .chain(Some(file))
+        .map(|f| f.display().to_string())
+        .collect();
+    let message = format!("files include each other: {}", cycle.join(" -> "));
+    Some(Error::new(message, span))
+}
+
+/// included finds the path of the file an expression includes, if it's an include.
+pub(crate) fn included(expr: &Expr) -> Option<&str> {
+    match &expr.item {
+        ExprKind::Call { name: i, args } if i.item.0 == "include" => match args.as_slice() {
+            [Spanned {
+                item: ExprKind::Str(path),
+                ..
+            }] => Some(path),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// is_definition checks whether some code defines something, rather than being an expression.
+///
+/// This is decided like in blocks, so imports and exports count as definitions.
+pub fn is_definition(code: &Code) -> bool {
+    match parse_block(code) {
+        Ok(statements) => matches!(statements.first(), Some(Statement::Definition(_))),
+        // Code that fails to parse could still be meant as a definition.
+        Err(_) => lexer::Lexer::new(&code.src)
+            .flatten()
+            .any(|tok| tok.item == lexer::Token::Is),
+    }
+}
+
+/// Values parse from literals, like `42`, `"Ada"`, or `nil`.
+impl FromStr for Value {
+    type Err = Error;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let expr = parse_expr(&Code::from(src))?;
+        match expr.item {
+            ExprKind::Nil => Ok(Value::Nil),
+            ExprKind::Int(i) => Ok(Value::Int(i)),
+            ExprKind::Str(s) => Ok(Value::Str(s)),
+            _ => Err(Error::new(
+                "expected a number, a string, or nil".to_string(),
+                expr.span,
+            )),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -69,241 +399,901 @@ impl Values {
         self.scopes.last_mut().unwrap().insert(ident, value);
     }
 
-    fn get(&mut self, ident: Ident) -> Value {
-        self.scopes
-            .last()
-            .unwrap()
-            .get(&ident)
-            .cloned()
-            .unwrap_or(Value::Nil)
+    fn get(&self, ident: &Ident) -> Option<&Value> {
+        self.scopes.last().unwrap().get(ident)
+    }
+}
+
+/// The default for how deeply calls can nest.
+///
+/// This keeps clear of overflowing a stack of 2 MiB, the default for threads spawned
+/// by Rust, even in debug builds. Raising it needs a larger stack.
+pub const DEFAULT_MAX_DEPTH: usize = 250;
+
+/// How deeply lists and maps can be nested in each other.
+///
+/// Values are cloned, compared, displayed and dropped recursively, so this keeps them
+/// from overflowing the stack, like [`DEFAULT_MAX_DEPTH`] does for calls.
+pub const MAX_VALUE_DEPTH: usize = 100;
+
+/// Limits bound the resources used to run code, making it an error to go past them.
+///
+/// Fuel and time are counted for each definition or expression, or for a whole
+/// document when rendering one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    /// How many expressions can be evaluated.
+    pub fuel: Option<u64>,
+    /// How deeply calls can nest, counting both calls to functions made by the
+    /// functions being called, and calls written inside of each other. Tail calls don't count.
+    pub max_depth: Option<usize>,
+    /// How many bytes of markdown rendering can produce.
+    pub max_output: Option<usize>,
+    /// How long running can take.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            fuel: None,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            max_output: None,
+            timeout: None,
+        }
+    }
+}
+
+/// A Budget tracks the fuel and time used since we started running some code.
+#[derive(Clone, Copy, Debug)]
+struct Budget {
+    steps: u64,
+    deadline: Option<Instant>,
+}
+
+/// A Module holds what a file imported with a name defines.
+#[derive(Clone, Debug)]
+struct Module {
+    file: PathBuf,
+    values: HashMap<Ident, Value>,
+    funcs: HashMap<Ident, Function>,
+    /// The definitions other files can use, if the module lists them.
+    exports: Option<Vec<Ident>>,
+}
+
+impl Module {
+    fn exports(&self, ident: &Ident) -> bool {
+        self.exports.as_ref().is_none_or(|e| e.contains(ident))
+    }
+
+    /// func finds one of the functions of a module, which keeps the module to look
+    /// up the functions its body calls, wherever it ends up.
+    fn func(self: &Arc<Self>, ident: &Ident) -> Option<Function> {
+        self.funcs.get(ident).map(|f| self.attach(f))
+    }
+
+    fn attach(self: &Arc<Self>, func: &Function) -> Function {
+        let mut func = func.clone();
+        if let Function::Defined {
+            module: m @ None, ..
+        } = &mut func
+        {
+            *m = Some(self.clone());
+        }
+        func
     }
 }
 
+/// The prelude, defining functions every document can use.
+const PRELUDE: &str = include_str!("prelude.md");
+/// The name of the file the prelude comes from, which doesn't exist.
+const PRELUDE_FILE: &str = "<prelude>";
+
+/// The chunks of a document, shared between the places using them.
+pub(crate) type Chunks = Arc<Vec<Spanned<DocumentChunk>>>;
+
+/// An Interpreter runs code, remembering the values and functions it defines.
 #[derive(Clone, Debug)]
 pub struct Interpreter {
     values: Values,
     funcs: HashMap<Ident, Function>,
     tail_args: Option<Vec<Value>>,
+    limits: Limits,
+    budget: Option<Budget>,
+    /// How many calls we're currently inside of.
+    depth: usize,
+    /// The files whose code we're running, with the innermost last.
+    files: Vec<PathBuf>,
+    /// Every file we've imported or included, parsed.
+    parsed: HashMap<PathBuf, Chunks>,
+    /// Every file we've imported as a module, run.
+    modules: HashMap<PathBuf, Arc<Module>>,
+    /// The module whose functions we're running, if any.
+    module: Option<Arc<Module>>,
+    /// The file imported without a name whose definitions we're running, if any.
+    importing: Option<PathBuf>,
+    /// The names given to the modules imported at the top level, along with their files.
+    aliases: HashMap<Ident, PathBuf>,
+    /// The definitions the code we're running exports, if it lists them.
+    exports: Option<Vec<Ident>>,
+    /// The functions every module starts out with: the native functions, and the prelude.
+    base: HashMap<Ident, Function>,
 }
 
 impl Interpreter {
+    /// Create an interpreter with nothing defined but the builtin functions, and the prelude.
     pub fn new() -> Self {
-        Self {
+        let mut interpreter = Self::without_prelude();
+        let chunks = Arc::new(DocParser::new(DocLexer::new(PRELUDE)).collect());
+        let file = PathBuf::from(PRELUDE_FILE);
+        let span = Span::default();
+        let module = interpreter
+            .run_module(file.clone(), &chunks, PRELUDE_FILE, span)
+            .expect("failed to run the prelude");
+        interpreter
+            .expose(&module, None, span)
+            .expect("failed to run the prelude");
+        interpreter.base = interpreter.funcs.clone();
+        interpreter
+    }
+
+    /// Create an interpreter with nothing defined but the builtin functions.
+    pub fn without_prelude() -> Self {
+        let mut interpreter = Self {
             values: Values::new(),
             funcs: HashMap::new(),
             tail_args: None,
-        }
+            limits: Limits::default(),
+            budget: None,
+            depth: 0,
+            files: Vec::new(),
+            parsed: HashMap::new(),
+            modules: HashMap::new(),
+            module: None,
+            importing: None,
+            aliases: HashMap::new(),
+            exports: None,
+            base: HashMap::new(),
+        };
+        builtins::register(&mut interpreter);
+        markdown::register(&mut interpreter);
+        interpreter
     }
 
-    fn eval_definition(&mut self, def: Definition) {
+    fn eval_definition(&mut self, def: Definition) -> Result<(), Error> {
         match def {
-            Definition::Value(i, e) => {
-                let v = self.eval_expr(None, e);
-                self.values.put(i, v);
+            Definition::Value { name: i, body: e } => {
+                let v = self.eval_expr(None, e)?;
+                self.values.put(i.item, v);
             }
-            Definition::Func(name, args, body) => {
-                self.funcs.insert(name, Function { args, body });
+            Definition::Func { name, args, body } => {
+                // Files imported without a name can't both define a function.
+                if let Some(file) = &self.importing {
+                    if let Some(Function::Defined {
+                        module: None,
+                        imported,
+                        ..
+                    }) = self.funcs.get(&name.item)
+                    {
+                        if imported.as_ref() != Some(file) {
+                            let message =
+                                format!("`{}` is already defined, so it's ambiguous", name.item.0);
+                            return Err(Error::new(message, name.span));
+                        }
+                    }
+                }
+                let func = Function::Defined {
+                    args: args.into_iter().map(|a| a.item).collect(),
+                    body: Arc::new(body),
+                    module: None,
+                    imported: self.importing.clone(),
+                };
+                self.funcs.insert(name.item, func);
+            }
+            Definition::Import {
+                path,
+                path_span: span,
+                alias: None,
+            } => {
+                let (file, chunks) = self.load_file(&path, span)?;
+                let importing = self.importing.replace(file.clone());
+                let result = self.run_definitions(file, &chunks, &path, span);
+                self.importing = importing;
+                result?;
+            }
+            Definition::Import {
+                path,
+                path_span: span,
+                alias: Some(name),
+            } => self.import_module(&path, span, name.item)?,
+            Definition::Export { names } => {
+                let names = names.into_iter().map(|n| n.item);
+                self.exports.get_or_insert_with(Vec::new).extend(names)
             }
         }
+        Ok(())
     }
 
-    fn accumulate<A, T>(
+    /// run_definitions runs the definitions in another file, ignoring the rest.
+    fn run_definitions(
         &mut self,
-        args: Vec<Expr>,
-        extract: impl Fn(Value) -> Option<T>,
-        init: A,
-        combine: impl Fn(A, T) -> A,
-        wrap: impl Fn(A) -> Value,
-    ) -> Value {
-        let mut acc = init;
-        for x in args {
-            match extract(self.eval_expr(None, x)) {
-                None => return Value::Nil,
-                Some(v) => acc = combine(acc, v),
+        file: PathBuf,
+        chunks: &Chunks,
+        path: &str,
+        span: Span,
+    ) -> Result<(), Error> {
+        self.enter_file(file);
+        let mut result = Ok(());
+        for chunk in chunks.iter() {
+            if let Err(e) = self.chunk_definitions(&chunk.item) {
+                result = Err(Error::new(format!("{}: {}", path, e), span));
+                break;
             }
         }
-        wrap(acc)
+        self.exit_file();
+        result
     }
 
-    fn function_call(&mut self, ident: Ident, arg_values: Vec<Value>) -> Value {
-        match self.funcs.get(&ident) {
-            None => Value::Nil,
-            Some(Function { args, body }) => {
-                let args = args.clone();
-                let body = body.clone();
+    /// run_module runs the definitions in a file on their own, keeping them as a module.
+    ///
+    /// Modules start out with nothing but the native functions and the prelude.
+    fn run_module(
+        &mut self,
+        file: PathBuf,
+        chunks: &Chunks,
+        path: &str,
+        span: Span,
+    ) -> Result<Arc<Module>, Error> {
+        let values = mem::replace(&mut self.values, Values::new());
+        let funcs = mem::replace(&mut self.funcs, self.base.clone());
+        let aliases = mem::take(&mut self.aliases);
+        let exports = mem::take(&mut self.exports);
+        let current_module = self.module.take();
+        let importing = self.importing.take();
+        let result = self.run_definitions(file.clone(), chunks, path, span);
+        self.module = current_module;
+        self.importing = importing;
+        let module = Module {
+            file: file.clone(),
+            values: mem::replace(&mut self.values, values).scopes.swap_remove(0),
+            funcs: mem::replace(&mut self.funcs, funcs),
+            exports: mem::replace(&mut self.exports, exports),
+        };
+        self.aliases = aliases;
+        result?;
 
-                self.tail_args = Some(arg_values);
-                self.values.enter();
+        for name in module.exports.iter().flatten() {
+            if !module.values.contains_key(name) && !module.funcs.contains_key(name) {
+                let message = format!("{}: exports `{}`, which it doesn't define", path, name.0);
+                return Err(Error::new(message, span));
+            }
+        }
+        let module = Arc::new(module);
+        self.modules.insert(file, module.clone());
+        Ok(module)
+    }
 
-                let mut ret = Value::Nil;
-                while let Some(arg_values) = mem::take(&mut self.tail_args) {
-                    for (i, arg_name) in args.iter().enumerate() {
-                        self.values.put(
-                            arg_name.clone(),
-                            arg_values.get(i).unwrap_or(&Value::Nil).clone(),
-                        )
-                    }
-                    ret = self.eval_expr(Some(ident.clone()), body.clone());
+    /// import_module makes what a file exports visible with a name in front of it.
+    ///
+    /// Files are only run the first time they're imported this way.
+    fn import_module(&mut self, path: &str, span: Span, name: Ident) -> Result<(), Error> {
+        let (file, chunks) = self.load_file(path, span)?;
+        if let Some(other) = self.aliases.get(&name) {
+            if *other != file {
+                let message = format!(
+                    "`{}` already names the module `{}`",
+                    name.0,
+                    other.display()
+                );
+                return Err(Error::new(message, span));
+            }
+        }
+        let module = match self.modules.get(&file) {
+            Some(module) => module.clone(),
+            None => self.run_module(file.clone(), &chunks, path, span)?,
+        };
+        self.expose(&module, Some(&name), span)?;
+        self.aliases.insert(name, file);
+        Ok(())
+    }
+
+    /// expose defines what a module exports, with a name in front of it if there's one.
+    fn expose(
+        &mut self,
+        module: &Arc<Module>,
+        name: Option<&Ident>,
+        span: Span,
+    ) -> Result<(), Error> {
+        let qualified = |i: &Ident| match name {
+            None => i.clone(),
+            Some(name) => Ident(format!("{}/{}", name.0, i.0)),
+        };
+        let funcs = module
+            .funcs
+            .iter()
+            .filter(|(i, f)| matches!(f, Function::Defined { .. }) && module.exports(i));
+        for (i, f) in funcs {
+            let qualified = qualified(i);
+            let defined_elsewhere = match self.funcs.get(&qualified) {
+                None => false,
+                Some(Function::Defined { module: m, .. }) => {
+                    m.as_ref().map(|m| &m.file) != Some(&module.file)
                 }
+                Some(Function::Native { .. } | Function::Apply { .. }) => true,
+            };
+            if defined_elsewhere {
+                let message = format!("`{}` is already defined, so it's ambiguous", qualified.0);
+                return Err(Error::new(message, span));
+            }
+            self.funcs.insert(qualified, module.attach(f));
+        }
+        for (i, v) in module.values.iter().filter(|(i, _)| module.exports(i)) {
+            self.values.put(qualified(i), v.clone());
+        }
+        Ok(())
+    }
+
+    /// lookup finds the function with some name, in the module whose functions we're running.
+    fn lookup(&self, ident: &Ident) -> Option<Function> {
+        match &self.module {
+            None => self.funcs.get(ident).cloned(),
+            Some(m) => m.func(ident),
+        }
+    }
 
+    /// load_file finds a file imported or included by the code we're running, and parses it.
+    ///
+    /// Files are only parsed the first time they're loaded. This fails if the
+    /// file is already being run, since it would end up including itself.
+    pub(crate) fn load_file(&mut self, path: &str, span: Span) -> Result<(PathBuf, Chunks), Error> {
+        let file = resolve(self.files.last().map(PathBuf::as_path), path);
+        let file = fs::canonicalize(&file)
+            .map_err(|e| Error::new(format!("failed to read `{}`: {}", path, e), span))?;
+        if let Some(e) = cycle(&self.files, &file, span) {
+            return Err(e);
+        }
+        if let Some(chunks) = self.parsed.get(&file) {
+            return Ok((file, chunks.clone()));
+        }
+        let src = fs::read_to_string(&file)
+            .map_err(|e| Error::new(format!("failed to read `{}`: {}", path, e), span))?;
+        let chunks = Arc::new(DocParser::new(DocLexer::new(&src)).collect::<Vec<_>>());
+        self.parsed.insert(file.clone(), chunks.clone());
+        Ok((file, chunks))
+    }
+
+    /// enter_file starts running the code of a file, returned by load_file.
+    pub(crate) fn enter_file(&mut self, file: PathBuf) {
+        self.files.push(file);
+    }
+
+    pub(crate) fn exit_file(&mut self) {
+        self.files.pop();
+    }
+
+    /// current_file is the file whose code we're running, if we know it.
+    pub(crate) fn current_file(&self) -> Option<&Path> {
+        self.files.last().map(PathBuf::as_path)
+    }
+
+    /// set_file tells the interpreter which file the code it runs comes from.
+    ///
+    /// The paths of the files it imports and includes are relative to that file,
+    /// rather than to the current directory.
+    pub fn set_file(&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        self.files = vec![fs::canonicalize(&path).unwrap_or(path)];
+    }
+
+    /// clear_file forgets the file given to set_file, so that paths are relative
+    /// to the current directory again.
+    pub fn clear_file(&mut self) {
+        self.files.clear();
+    }
+
+    /// loaded_files lists every file imported or included so far.
+    pub fn loaded_files(&self) -> impl Iterator<Item = &Path> {
+        self.parsed.keys().map(PathBuf::as_path)
+    }
+
+    fn run_body(
+        &mut self,
+        args: &[Ident],
+        body: &Arc<Expr>,
+        arg_values: Vec<Value>,
+    ) -> Result<Value, Error> {
+        self.tail_args = Some(arg_values);
+        let mut ret = Value::Nil;
+        while let Some(arg_values) = mem::take(&mut self.tail_args) {
+            let mut arg_values = arg_values.into_iter();
+            for arg_name in args {
+                self.values
+                    .put(arg_name.clone(), arg_values.next().unwrap_or(Value::Nil))
+            }
+            ret = self.eval_expr(Some(body.clone()), body.as_ref().clone())?;
+        }
+        Ok(ret)
+    }
+
+    /// resolve finds the function a call refers to.
+    fn resolve(&self, ident: &Ident) -> Option<Function> {
+        // Values can hold functions, which take precedence over the functions with the same name.
+        match self.values.get(ident) {
+            Some(Value::Func(f)) => Some(f.func.as_ref().clone()),
+            _ => self.lookup(ident),
+        }
+    }
+
+    fn function_call(
+        &mut self,
+        ident: &Ident,
+        func: Option<Function>,
+        arg_values: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, Error> {
+        match func {
+            None => Ok(Value::Nil),
+            Some(Function::Native { arity, .. } | Function::Apply { arity, .. })
+                if !arity.accepts(arg_values.len()) =>
+            {
+                let message = format!(
+                    "`{}` takes {}, but was given {}",
+                    ident.0,
+                    arity.describe(),
+                    arg_values.len()
+                );
+                Err(Error::new(message, span))
+            }
+            // Native functions don't know where they were called from, so their errors point at the call.
+            Some(Function::Native { func, .. }) => {
+                func(&arg_values).map_err(|e| Error::new(e.message, span))
+            }
+            Some(Function::Apply { func, .. }) => func(self, &arg_values, span),
+            Some(Function::Defined {
+                args, body, module, ..
+            }) => {
+                let module = mem::replace(&mut self.module, module);
+
+                self.values.enter();
+                let ret = self.run_body(&args, &body, arg_values);
                 self.values.exit();
+                self.module = module;
+                if ret.is_err() {
+                    self.tail_args = None;
+                }
                 ret
             }
         }
     }
 
-    fn call(&mut self, current_func: Option<Ident>, ident: Ident, args: Vec<Expr>) -> Value {
-        match ident.0.as_str() {
-            "+" => self.accumulate(
-                args,
-                |x| match x {
-                    Value::Int(i) => Some(i),
-                    _ => None,
-                },
-                0,
-                |x, y| x + y,
-                |x| Value::Int(x),
-            ),
-            "*" => self.accumulate(
-                args,
-                |x| match x {
-                    Value::Int(i) => Some(i),
-                    _ => None,
-                },
-                1,
-                |x, y| x * y,
-                |x| Value::Int(x),
-            ),
-            "-" => self.accumulate(
-                args,
-                |x| match x {
-                    Value::Int(i) => Some(i),
-                    _ => None,
-                },
-                None,
-                |x, y| match x {
-                    None => Some(y),
-                    Some(acc) => Some(acc - y),
-                },
-                |x| Value::Int(x.unwrap_or(0)),
-            ),
-            "/" => self.accumulate(
-                args,
-                |x| match x {
-                    Value::Int(i) => Some(i),
-                    _ => None,
-                },
-                None,
-                |x, y| match x {
-                    None => Some(y),
-                    Some(acc) => Some(acc / y),
-                },
-                |x| Value::Int(x.unwrap_or(1)),
-            ),
-            "=" => self.accumulate(
-                args,
-                |x| match x {
-                    Value::Int(i) => Some(i),
-                    _ => None,
-                },
-                (true, None),
-                |(equal, x), y| match x {
-                    None => (true, Some(y)),
-                    Some(acc) => (equal && acc == y, Some(y)),
-                },
-                |(equal, _)| Value::Int(if equal { 1 } else { 0 }),
-            ),
-            ">" => self.accumulate(
-                args,
-                |x| match x {
-                    Value::Int(i) => Some(i),
-                    _ => None,
-                },
-                (true, None),
-                |(good, x), y| match x {
-                    None => (true, Some(y)),
-                    Some(acc) => (good && acc > y, Some(acc)),
-                },
-                |(good, _)| Value::Int(if good { 1 } else { 0 }),
-            ),
-            "<" => self.accumulate(
-                args,
-                |x| match x {
-                    Value::Int(i) => Some(i),
-                    _ => None,
-                },
-                (true, None),
-                |(good, x), y| match x {
-                    None => (true, Some(y)),
-                    Some(acc) => (good && acc < y, Some(acc)),
-                },
-                |(good, _)| Value::Int(if good { 1 } else { 0 }),
-            ),
-            "and" => self.accumulate(
-                args,
-                |x| Some(x.truthy()),
-                true,
-                |x, y| x && y,
-                |x| Value::Int(if x { 1 } else { 0 }),
-            ),
-            "or" => self.accumulate(
-                args,
-                |x| Some(x.truthy()),
-                false,
-                |x, y| x || y,
-                |x| Value::Int(if x { 1 } else { 0 }),
-            ),
-            "not" => Value::Int(
-                if args
-                    .get(0)
-                    .map_or(Value::Nil, |x| self.eval_expr(None, x.clone()))
-                    .truthy()
-                {
-                    0
-                } else {
-                    1
+    fn call(
+        &mut self,
+        current_func: Option<Arc<Expr>>,
+        ident: Ident,
+        args: Vec<Expr>,
+        span: Span,
+    ) -> Result<Value, Error> {
+        if ident.0 == "include" {
+            let message = "`include` only works on its own, as the whole interpolation".to_string();
+            return Err(Error::new(message, span));
+        }
+        // if only evaluates one of its branches, so it can't be a function.
+        if ident.0 == "if" {
+            let condition = match args.first() {
+                None => Value::Nil,
+                Some(x) => self.eval_expr(None, x.clone())?,
+            };
+            let branch = if condition.truthy() { 1 } else { 2 };
+            return match args.get(branch) {
+                None => Ok(Value::Nil),
+                Some(x) => self.eval_expr(current_func, x.clone()),
+            };
+        }
+        let arg_values = args
+            .into_iter()
+            .map(|x| self.eval_expr(None, x))
+            .collect::<Result<Vec<_>, _>>()?;
+        let func = self.resolve(&ident);
+        // A call to the function we're in, as the last thing it does, reuses the call we're in.
+        if let (Some(current), Some(Function::Defined { body, .. })) = (&current_func, &func) {
+            if Arc::ptr_eq(current, body) {
+                self.tail_args = Some(arg_values);
+                return Ok(Value::Nil);
+            }
+        }
+        self.function_call(&ident, func, arg_values, span)
+    }
+
+    /// spend uses up one step of fuel, checking that we're still within our limits.
+    fn spend(&mut self, span: Span) -> Result<(), Error> {
+        let budget = match &mut self.budget {
+            None => return Ok(()),
+            Some(budget) => budget,
+        };
+        budget.steps += 1;
+        if let Some(fuel) = self.limits.fuel {
+            if budget.steps > fuel {
+                let message = format!("ran out of fuel after {} steps", fuel);
+                return Err(Error::new(message, span));
+            }
+        }
+        if let (Some(deadline), Some(timeout)) = (budget.deadline, self.limits.timeout) {
+            if Instant::now() > deadline {
+                let message = format!("timed out after {:?}", timeout);
+                return Err(Error::new(message, span));
+            }
+        }
+        Ok(())
+    }
+
+    /// start_budget starts counting fuel and time, unless we already are.
+    ///
+    /// This returns whether or not we started, to pass on to end_budget.
+    pub(crate) fn start_budget(&mut self) -> bool {
+        if self.budget.is_some() {
+            return false;
+        }
+        self.budget = Some(Budget {
+            steps: 0,
+            deadline: self.limits.timeout.map(|t| Instant::now() + t),
+        });
+        true
+    }
+
+    pub(crate) fn end_budget(&mut self, started: bool) {
+        if started {
+            self.budget = None;
+        }
+    }
+
+    /// eval_expr evaluates an expression, which is what the function with the body
+    /// current_func returns, if there's one, so that it can call itself as a tail call.
+    fn eval_expr(&mut self, current_func: Option<Arc<Expr>>, expr: Expr) -> Result<Value, Error> {
+        self.spend(expr.span)?;
+        match expr.item {
+            ExprKind::Nil => Ok(Value::Nil),
+            ExprKind::Int(i) => Ok(Value::Int(i)),
+            ExprKind::Str(s) => Ok(Value::Str(s)),
+            ExprKind::Ident(i) => Ok(match self.values.get(&i) {
+                Some(v) => v.clone(),
+                None => match self.lookup(&i) {
+                    None => Value::Nil,
+                    Some(f) => Value::Func(FuncRef {
+                        name: i.0,
+                        func: Arc::new(f),
+                    }),
                 },
-            ),
-            "if" => {
-                let condition = args
-                    .get(0)
-                    .map_or(Value::Nil, |x| self.eval_expr(None, x.clone()));
-                if condition.truthy() {
-                    args.get(1)
-                        .map_or(Value::Nil, |x| self.eval_expr(current_func, x.clone()))
-                } else {
-                    args.get(2)
-                        .map_or(Value::Nil, |x| self.eval_expr(current_func, x.clone()))
+            }),
+            ExprKind::Call { name: i, args } => {
+                if let Some(max_depth) = self.limits.max_depth {
+                    if self.depth >= max_depth {
+                        let message = format!("calls are nested more than {} deep", max_depth);
+                        return Err(Error::new(message, expr.span));
+                    }
                 }
+                self.depth += 1;
+                let ret = self.call(current_func, i.item, args, expr.span);
+                self.depth -= 1;
+                ret
             }
-            _ => {
-                let arg_values = args.into_iter().map(|x| self.eval_expr(None, x)).collect();
-                if Some(&ident) == current_func.as_ref() {
-                    self.tail_args = Some(arg_values);
-                    Value::Nil
-                } else {
-                    self.function_call(ident, arg_values)
-                }
+        }
+    }
+
+    /// limits returns the limits on the resources used to run code.
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// set_limits changes the limits on the resources used to run code.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// define gives a value to a name, like a definition in code would.
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.values.put(Ident(name.to_string()), value);
+    }
+
+    /// value_names lists the names of the values defined at the top level.
+    pub(crate) fn value_names(&self) -> impl Iterator<Item = &Ident> {
+        self.values.scopes[0].keys()
+    }
+
+    /// register makes a Rust function callable from code, under some name.
+    ///
+    /// Functions get the values of their arguments, however many were given.
+    /// This replaces any function with the same name, including builtins, and
+    /// later definitions in code can replace it in turn. Modules can use it too.
+    pub fn register<F>(&mut self, name: &str, func: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, Error> + Send + Sync + 'static,
+    {
+        self.register_with_arity(name, Arity::Any, func);
+    }
+
+    pub(crate) fn register_with_arity<F>(&mut self, name: &str, arity: Arity, func: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, Error> + Send + Sync + 'static,
+    {
+        let func = Function::Native {
+            arity,
+            func: Arc::new(func),
+        };
+        self.funcs.insert(Ident(name.to_string()), func.clone());
+        self.base.insert(Ident(name.to_string()), func);
+    }
+
+    pub(crate) fn register_apply(&mut self, name: &str, arity: Arity, func: ApplyFn) {
+        let func = Function::Apply { arity, func };
+        self.funcs.insert(Ident(name.to_string()), func.clone());
+        self.base.insert(Ident(name.to_string()), func);
+    }
+
+    /// call_value calls a function given as a value, like the functions given to `map`.
+    ///
+    /// Like calling a function that doesn't exist, calling anything else gives nil.
+    pub(crate) fn call_value(
+        &mut self,
+        func: &Value,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, Error> {
+        match func {
+            Value::Func(f) => {
+                let ident = Ident(f.name.clone());
+                self.function_call(&ident, Some(f.func.as_ref().clone()), args, span)
             }
+            _ => Ok(Value::Nil),
         }
     }
 
-    fn eval_expr(&mut self, current_func: Option<Ident>, expr: Expr) -> Value {
-        match expr {
-            Expr::Nil => Value::Nil,
-            Expr::Int(i) => Value::Int(i),
-            Expr::Ident(i) => self.values.get(i),
-            Expr::Call(i, args) => self.call(current_func, i, args),
+    /// arity finds how many arguments a function takes, if it exists.
+    pub(crate) fn arity(&self, ident: &Ident) -> Option<Arity> {
+        match self.funcs.get(ident)? {
+            Function::Native { arity, .. } | Function::Apply { arity, .. } => Some(*arity),
+            Function::Defined { args, .. } => Some(Arity::Exactly(args.len())),
         }
     }
 
-    pub fn definition(&mut self, code: Code) -> Result<(), String> {
-        let def = new_parser(&code).top_level_definition()?;
-        Ok(self.eval_definition(def))
+    /// definition runs some code defining a value or a function.
+    pub fn definition(&mut self, code: impl Into<Code>) -> Result<(), Error> {
+        let def = parse_definition(&code.into())?;
+        let started = self.start_budget();
+        let result = self.eval_definition(def);
+        self.end_budget(started);
+        result
+    }
+
+    /// expr evaluates an expression, returning its value.
+    pub fn expr(&mut self, code: impl Into<Code>) -> Result<Value, Error> {
+        let expr = parse_expr(&code.into())?;
+        self.eval(expr)
+    }
+
+    /// eval evaluates an expression which was already parsed.
+    pub(crate) fn eval(&mut self, expr: Expr) -> Result<Value, Error> {
+        let started = self.start_budget();
+        let result = self.eval_expr(None, expr);
+        self.end_budget(started);
+        result
+    }
+
+    /// statement runs a definition, or evaluates an expression, returning its value.
+    pub(crate) fn statement(&mut self, statement: Statement) -> Result<Option<Value>, Error> {
+        let started = self.start_budget();
+        let result = match statement {
+            Statement::Definition(def) => self.eval_definition(def).map(|()| None),
+            Statement::Expr(expr) => self.eval_expr(None, expr).map(Some),
+        };
+        self.end_budget(started);
+        result
+    }
+
+    /// chunk_definitions runs the definitions in a chunk of a document, stopping at the first error.
+    fn chunk_definitions(&mut self, chunk: &DocumentChunk) -> Result<(), Error> {
+        for def in definitions(chunk)? {
+            self.statement(Statement::Definition(def))?;
+        }
+        Ok(())
+    }
+
+    /// load_definitions runs the definitions in a document, ignoring the rest.
+    pub fn load_definitions(&mut self, src: &str) -> Result<(), Errors> {
+        let chunks = DocParser::new(DocLexer::new(src));
+        let mut errors = Vec::new();
+        for chunk in chunks {
+            if let Err(e) = self.chunk_definitions(&chunk.item) {
+                errors.push(e);
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Errors(errors))
+        }
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn interpreter(defs: &str) -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        interpreter.load_definitions(defs).unwrap();
+        interpreter
+    }
+
+    fn error(result: Result<Value, Error>) -> String {
+        result.unwrap_err().message
+    }
+
+    #[test]
+    fn fuel_stops_loops() {
+        let mut interpreter = interpreter("<!-- (loop n) is (loop (+ n 1)) -->");
+        interpreter.set_limits(Limits {
+            fuel: Some(10_000),
+            ..Default::default()
+        });
+        assert_eq!(
+            error(interpreter.expr("(loop 0)")),
+            "ran out of fuel after 10000 steps"
+        );
+    }
+
+    #[test]
+    fn depth() {
+        let mut interpreter =
+            interpreter("<!-- (deep n) is (if (= n 0) 0 (+ 1 (deep (- n 1)))) -->");
+        interpreter.set_limits(Limits {
+            max_depth: Some(20),
+            ..Default::default()
+        });
+        assert_eq!(interpreter.expr("(deep 5)"), Ok(Value::Int(5)));
+        assert_eq!(
+            error(interpreter.expr("(deep 50)")),
+            "calls are nested more than 20 deep"
+        );
+
+        // Calls written inside of each other count too.
+        let nested = |n| format!("{}0{}", "(+ 1 ".repeat(n), ")".repeat(n));
+        assert_eq!(interpreter.expr(nested(15)), Ok(Value::Int(15)));
+        assert_eq!(
+            error(interpreter.expr(nested(25))),
+            "calls are nested more than 20 deep"
+        );
+    }
+
+    #[test]
+    fn tail_calls() {
+        let mut interpreter = interpreter(
+            "<!-- (count n) is (if (= n 0) 0 (count (- n 1))) -->\n\
+             <!-- (through f n) is (if (= n 0) 0 (f f (- n 1))) -->\n\
+             <!-- (ping f g n) is (if (= n 0) 0 (f g f (- n 1))) -->\n\
+             <!-- (pong f g n) is (if (= n 0) 0 (f g f (- n 1))) -->",
+        );
+        assert_eq!(interpreter.expr("(count 10000)"), Ok(Value::Int(0)));
+        // Calling the same function through a value is still a tail call.
+        assert_eq!(
+            interpreter.expr("(through through 10000)"),
+            Ok(Value::Int(0))
+        );
+        assert_eq!(
+            interpreter.expr("(ping ping ping 10000)"),
+            Ok(Value::Int(0))
+        );
+        // Calling another function isn't, even one with the same body.
+        assert_eq!(interpreter.expr("(ping pong ping 10)"), Ok(Value::Int(0)));
+        assert_eq!(
+            error(interpreter.expr("(ping pong ping 10000)")),
+            "calls are nested more than 250 deep"
+        );
+    }
+
+    #[test]
+    fn modules() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_file("examples/008_modules.md");
+        let defs = "<!-- (import \"lib/money.md\" as money) -->\n<!-- (cents-in x) is 0 -->";
+        interpreter.load_definitions(defs).unwrap();
+        // Module functions call the functions of their module, not those of the document.
+        assert_eq!(
+            interpreter.expr("(money/total (money/dollars 12) 3)"),
+            Ok(Value::Int(3600))
+        );
+        assert_eq!(
+            interpreter.expr("(map money/dollars (list 1 2))"),
+            Ok(Value::List(vec![Value::Int(100), Value::Int(200)]))
+        );
+        assert_eq!(interpreter.expr("(cents-in 12)"), Ok(Value::Int(0)));
+        // Unknown functions give nil, like the ones a module doesn't export.
+        assert_eq!(interpreter.expr("(money/cents-in 12)"), Ok(Value::Nil));
+    }
+
+    #[test]
+    fn ambiguous_imports() {
+        let dir = env::temp_dir().join(format!("wahlbergdown-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.md"), "<!-- (f x) is 1 -->").unwrap();
+        fs::write(dir.join("b.md"), "<!-- (f x) is 2 -->").unwrap();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_file(dir.join("main.md"));
+        let twice =
+            interpreter.load_definitions("<!-- (import \"a.md\") -->\n<!-- (import \"a.md\") -->");
+        let both = interpreter.load_definitions("<!-- (import \"b.md\") -->");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(twice, Ok(()));
+        assert_eq!(
+            both.unwrap_err().0[0].message,
+            "b.md: `f` is already defined, so it's ambiguous"
+        );
+        assert_eq!(interpreter.expr("(f 0)"), Ok(Value::Int(1)));
+    }
+
+    #[test]
+    fn function_values() {
+        let mut interpreter = interpreter("<!-- (double x) is (* x 2) -->");
+        assert_eq!(interpreter.expr("(= double double)"), Ok(Value::Int(1)));
+        assert_eq!(interpreter.expr("(= sum sum)"), Ok(Value::Int(1)));
+        assert_eq!(interpreter.expr("(= map map)"), Ok(Value::Int(1)));
+        assert_eq!(interpreter.expr("(= + -)"), Ok(Value::Int(0)));
+        assert_eq!(interpreter.expr("(= double sum)"), Ok(Value::Int(0)));
+    }
+
+    #[test]
+    fn limits_on_values() {
+        let mut interpreter = interpreter(
+            "<!-- (nest n acc) is (if (= n 0) (len acc) (nest (- n 1) (list acc))) -->",
+        );
+        assert_eq!(interpreter.expr("(nest 100 nil)"), Ok(Value::Int(1)));
+        assert_eq!(
+            error(interpreter.expr("(nest 5000 nil)")),
+            "lists and maps are nested more than 100 deep"
+        );
+        assert_eq!(
+            error(interpreter.expr("99999999999999999999")),
+            "integer literal too large"
+        );
+        assert_eq!(
+            error(interpreter.expr("(* 9223372036854775807 2)")),
+            "integer overflow"
+        );
+    }
+
+    #[test]
+    fn parsed_code() {
+        let code = |src: &str| Code::new(src.to_string());
+        let import = parse_definition(&code("(import \"a.md\" as a)")).unwrap();
+        let json = serde_json::to_value(import).unwrap();
+        assert_eq!(json["value"]["path"], "a.md");
+        assert_eq!(json["value"]["alias"]["name"], "a");
+
+        let func = parse_definition(&code("(f x) is (g x)")).unwrap();
+        let json = serde_json::to_value(func).unwrap();
+        assert_eq!(json["value"]["args"][0]["name"], "x");
+        assert_eq!(json["value"]["body"]["value"]["name"]["name"], "g");
+
+        let comments = comments(&code("(f ; c  \n 1)"));
+        assert_eq!(comments[0].span, Span::new(3, 6));
     }
 
-    pub fn expr(&mut self, code: Code) -> Result<Value, String> {
-        let expr = new_parser(&code).top_level_expr()?;
-        Ok(self.eval_expr(None, expr))
+    #[test]
+    fn incomplete_code() {
+        let incomplete = |src: &str| incomplete(&Code::new(src.to_string()));
+        assert!(incomplete("(f a) is"));
+        assert!(incomplete("(f a) is ; the body is next"));
+        assert!(incomplete("(f (g a)"));
+        assert!(!incomplete("(f a) is a"));
+        assert!(!incomplete("x"));
     }
 }
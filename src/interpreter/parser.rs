@@ -1,62 +1,150 @@
 use std::iter::Peekable;
 
+use serde::ser::{SerializeStruct, Serializer};
+use serde::Serialize;
+
+use crate::error::Error;
 use crate::interpreter::lexer::{Lexer, Token};
+use crate::span::{Span, Spanned};
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Ident(pub String);
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum Expr {
+/// Identifiers serialize as `{"name": ...}`, so that their spans can go next to their names.
+impl Serialize for Ident {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut ident = serializer.serialize_struct("Ident", 1)?;
+        ident.serialize_field("name", &self.0)?;
+        ident.end()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value")]
+pub enum ExprKind {
     Nil,
     Int(i64),
+    Str(String),
     Ident(Ident),
-    Call(Ident, Vec<Expr>),
+    /// Calls a function, by its name.
+    Call {
+        name: Spanned<Ident>,
+        args: Vec<Expr>,
+    },
 }
 
-#[derive(Clone, Debug, PartialEq)]
+pub type Expr = Spanned<ExprKind>;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value")]
 pub enum Definition {
-    Value(Ident, Expr),
-    Func(Ident, Vec<Ident>, Expr),
+    Value {
+        name: Spanned<Ident>,
+        body: Expr,
+    },
+    Func {
+        name: Spanned<Ident>,
+        args: Vec<Spanned<Ident>>,
+        body: Expr,
+    },
+    /// Runs the definitions in another file, given by its path.
+    ///
+    /// With an alias, this is a module: its definitions are only visible with
+    /// that name in front of them, like `money/format`.
+    Import {
+        path: String,
+        path_span: Span,
+        alias: Option<Spanned<Ident>>,
+    },
+    /// Lists the definitions of a file that modules importing it can use.
+    Export {
+        names: Vec<Spanned<Ident>>,
+    },
+}
+
+/// A Statement is one of the definitions or expressions making up a block of code.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Statement {
+    Definition(Definition),
+    Expr(Expr),
 }
 
+/// How deeply calls can be nested inside of each other in code.
+///
+/// Parsing, checking and running code all recurse into nested calls, so this
+/// keeps them from overflowing the stack.
+pub const MAX_NESTING: usize = 200;
+
 #[derive(Clone, Debug)]
 pub struct Parser<'a> {
     tokens: Peekable<Lexer<'a>>,
+    /// The position just after the last token we've consumed.
+    pos: usize,
+    /// How many calls we're inside of.
+    nesting: usize,
 }
 
-pub type ParseResult<T> = Result<T, String>;
+pub type ParseResult<T> = Result<T, Error>;
 
 impl<'a> Parser<'a> {
     pub fn new(tokens: Lexer<'a>) -> Self {
         Self {
+            pos: tokens.pos(),
             tokens: tokens.peekable(),
+            nesting: 0,
+        }
+    }
+
+    /// skip_comments moves past any comments, which the parser ignores.
+    fn skip_comments(&mut self) {
+        while let Some(Ok(Spanned {
+            item: Token::Comment(_),
+            ..
+        })) = self.tokens.peek()
+        {
+            self.tokens.next();
         }
     }
 
     fn peek(&mut self) -> ParseResult<Option<&Token>> {
+        self.skip_comments();
         match self.tokens.peek() {
             None => Ok(None),
             Some(Err(e)) => Err(e.clone()),
-            Some(Ok(t)) => Ok(Some(t)),
+            Some(Ok(t)) => Ok(Some(&t.item)),
         }
     }
 
-    fn next(&mut self) -> ParseResult<Option<Token>> {
+    fn next(&mut self) -> ParseResult<Option<Spanned<Token>>> {
+        self.skip_comments();
         match self.tokens.next() {
             None => Ok(None),
-            Some(t) => Ok(Some(t?)),
+            Some(t) => {
+                let t = t?;
+                self.pos = t.span.end;
+                Ok(Some(t))
+            }
         }
     }
 
-    fn expect<T, F>(&mut self, matcher: F) -> ParseResult<T>
+    fn unexpected_eof(&self) -> Error {
+        Error::new("unexpected EOF".to_string(), Span::new(self.pos, self.pos))
+    }
+
+    fn unexpected(tok: &Spanned<Token>) -> Error {
+        Error::new(format!("unexpected token {:?}", tok.item), tok.span)
+    }
+
+    fn expect<T, F>(&mut self, matcher: F) -> ParseResult<Spanned<T>>
     where
         F: Fn(Token) -> Option<T>,
     {
         match self.next()? {
-            None => Err(format!("unexpected EOF")),
-            Some(tok) => match matcher(tok.clone()) {
-                None => Err(format!("unexpected token {:?}", tok)),
-                Some(t) => Ok(t),
+            None => Err(self.unexpected_eof()),
+            Some(tok) => match matcher(tok.item.clone()) {
+                None => Err(Self::unexpected(&tok)),
+                Some(t) => Ok(Spanned::new(t, tok.span)),
             },
         }
     }
@@ -64,30 +152,49 @@ impl<'a> Parser<'a> {
     fn expect_end(&mut self) -> ParseResult<()> {
         match self.next()? {
             None => Ok(()),
-            Some(tok) => Err(format!("unexpected token {:?}", tok)),
+            Some(tok) => Err(Self::unexpected(&tok)),
         }
     }
 
-    fn ident(&mut self) -> ParseResult<Ident> {
+    fn ident(&mut self) -> ParseResult<Spanned<Ident>> {
         self.expect(|x| match x {
             Token::Identifier(i) => Some(Ident(i)),
             _ => None,
         })
     }
 
-    fn call(&mut self) -> ParseResult<Expr> {
-        self.expect(|x| match x {
+    fn open_parens(&mut self) -> ParseResult<Span> {
+        let open = self.expect(|x| match x {
             Token::OpenParens => Some(()),
             _ => None,
         })?;
+        Ok(open.span)
+    }
+
+    fn call(&mut self) -> ParseResult<Expr> {
+        let start = self.open_parens()?;
+        if self.nesting >= MAX_NESTING {
+            let message = format!("calls are nested more than {} deep", MAX_NESTING);
+            return Err(Error::new(message, start));
+        }
         let ident = self.ident()?;
         let mut exprs = Vec::new();
+        // Errors stop parsing, so there's no need to count back down for them.
+        self.nesting += 1;
         loop {
             match self.peek()? {
-                None => return Err(format!("unexpected EOF")),
+                None => return Err(self.unexpected_eof()),
                 Some(Token::CloseParens) => {
                     self.next()?;
-                    return Ok(Expr::Call(ident, exprs));
+                    self.nesting -= 1;
+                    let span = start.to(Span::new(self.pos, self.pos));
+                    return Ok(Spanned::new(
+                        ExprKind::Call {
+                            name: ident,
+                            args: exprs,
+                        },
+                        span,
+                    ));
                 }
                 Some(_) => exprs.push(self.expr()?),
             }
@@ -96,43 +203,85 @@ impl<'a> Parser<'a> {
 
     fn expr(&mut self) -> ParseResult<Expr> {
         match self.peek()? {
-            None => Err(format!("unexpected EOF")),
-            Some(Token::Identifier(_)) => match self.next()? {
-                Some(Token::Identifier(i)) => Ok(Expr::Ident(Ident(i))),
-                _ => unreachable!(),
-            },
-            Some(Token::Int(i)) => {
-                let expr = Expr::Int(*i);
-                self.next()?;
-                Ok(expr)
-            }
-            Some(Token::Nil) => {
-                self.next()?;
-                Ok(Expr::Nil)
-            }
+            None => Err(self.unexpected_eof()),
             Some(Token::OpenParens) => self.call(),
-            Some(tok) => Err(format!("unexpected token {:?}", tok)),
+            Some(_) => {
+                let tok = self.next()?.unwrap();
+                let kind = match tok.item {
+                    Token::Identifier(i) => ExprKind::Ident(Ident(i)),
+                    Token::Int(i) => ExprKind::Int(i),
+                    Token::Str(s) => ExprKind::Str(s),
+                    Token::Nil => ExprKind::Nil,
+                    _ => return Err(Self::unexpected(&tok)),
+                };
+                Ok(Spanned::new(kind, tok.span))
+            }
         }
     }
 
-    fn func_definition(&mut self) -> ParseResult<Definition> {
+    fn is(&mut self) -> ParseResult<()> {
         self.expect(|x| match x {
-            Token::OpenParens => Some(()),
+            Token::Is => Some(()),
             _ => None,
         })?;
+        Ok(())
+    }
+
+    fn close_parens(&mut self) -> ParseResult<()> {
+        self.expect(|x| match x {
+            Token::CloseParens => Some(()),
+            _ => None,
+        })?;
+        Ok(())
+    }
+
+    /// import parses the rest of an import, after `(import`.
+    fn import(&mut self) -> ParseResult<Definition> {
+        let path = self.expect(|x| match x {
+            Token::Str(s) => Some(s),
+            _ => None,
+        })?;
+        let mut name = None;
+        if let Some(Token::Identifier(i)) = self.peek()? {
+            if i == "as" {
+                self.next()?;
+                name = Some(self.ident()?);
+            }
+        }
+        self.close_parens()?;
+        Ok(Definition::Import {
+            path: path.item,
+            path_span: path.span,
+            alias: name,
+        })
+    }
+
+    fn func_definition(&mut self) -> ParseResult<Definition> {
+        self.open_parens()?;
         let ident = self.ident()?;
+        // Functions can still be called import, as long as their first argument isn't a string.
+        if ident.item.0 == "import" {
+            if let Some(Token::Str(_)) = self.peek()? {
+                return self.import();
+            }
+        }
         let mut idents = Vec::new();
         loop {
             match self.peek()? {
-                None => return Err(format!("unexpected EOF")),
+                None => return Err(self.unexpected_eof()),
                 Some(Token::CloseParens) => {
                     self.next()?;
-                    self.expect(|x| match x {
-                        Token::Is => Some(()),
-                        _ => None,
-                    })?;
+                    // Exports look like functions without a body.
+                    if ident.item.0 == "export" && self.peek()? != Some(&Token::Is) {
+                        return Ok(Definition::Export { names: idents });
+                    }
+                    self.is()?;
                     let expr = self.expr()?;
-                    return Ok(Definition::Func(ident, idents, expr));
+                    return Ok(Definition::Func {
+                        name: ident,
+                        args: idents,
+                        body: expr,
+                    });
                 }
                 Some(_) => idents.push(self.ident()?),
             }
@@ -141,20 +290,38 @@ impl<'a> Parser<'a> {
 
     fn definition(&mut self) -> ParseResult<Definition> {
         match self.peek()? {
-            None => Err(format!("unexpected EOF")),
-            Some(Token::Identifier(_)) => match self.next()? {
-                Some(Token::Identifier(i)) => {
-                    self.expect(|x| match x {
-                        Token::Is => Some(()),
-                        _ => None,
-                    })?;
-                    let expr = self.expr()?;
-                    Ok(Definition::Value(Ident(i), expr))
-                }
-                _ => unreachable!(),
-            },
+            None => Err(self.unexpected_eof()),
             Some(Token::OpenParens) => self.func_definition(),
-            Some(tok) => Err(format!("unexpected token {:?}", tok)),
+            Some(_) => {
+                let ident = self.ident()?;
+                self.is()?;
+                let expr = self.expr()?;
+                Ok(Definition::Value {
+                    name: ident,
+                    body: expr,
+                })
+            }
+        }
+    }
+
+    /// statement parses a definition or an expression.
+    fn statement(&mut self) -> ParseResult<Statement> {
+        // Definitions start like expressions, so we look at what comes after one.
+        let mut ahead = self.clone();
+        let expr = ahead.expr()?;
+        let is_definition = ahead.peek()? == Some(&Token::Is)
+            || match &expr.item {
+                ExprKind::Call { name: i, args } if i.item.0 == "import" => {
+                    matches!(args.first().map(|a| &a.item), Some(ExprKind::Str(_)))
+                }
+                ExprKind::Call { name: i, .. } => i.item.0 == "export",
+                _ => false,
+            };
+        if is_definition {
+            self.definition().map(Statement::Definition)
+        } else {
+            *self = ahead;
+            Ok(Statement::Expr(expr))
         }
     }
 
@@ -169,4 +336,13 @@ impl<'a> Parser<'a> {
         self.expect_end()?;
         Ok(def)
     }
+
+    /// top_level_block parses a block of code, made of any number of statements.
+    pub fn top_level_block(&mut self) -> ParseResult<Vec<Statement>> {
+        let mut statements = Vec::new();
+        while self.peek()?.is_some() {
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
 }
@@ -0,0 +1,273 @@
+use crate::interpreter::lexer::Comment;
+use crate::interpreter::parser::{Definition, Expr, ExprKind};
+use crate::span::Spanned;
+
+/// The width we try to keep lines of code under.
+pub const WIDTH: usize = 80;
+/// How much we indent the arguments of calls that don't fit on one line.
+const INDENT: usize = 2;
+
+/// quote writes a string as a literal, escaping what needs to be.
+pub fn quote(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// flat_into prints an expression on a single line, along with the comments inside of it.
+fn flat_into(expr: &Expr, comments: &mut &[Spanned<Comment>], out: &mut String) {
+    match &expr.item {
+        ExprKind::Nil => out.push_str("nil"),
+        ExprKind::Int(i) => out.push_str(&i.to_string()),
+        ExprKind::Str(s) => out.push_str(&quote(s)),
+        ExprKind::Ident(i) => out.push_str(&i.0),
+        ExprKind::Call { name: i, args } => {
+            out.push('(');
+            out.push_str(&i.item.0);
+            for arg in args {
+                while let Some((c, rest)) = comments.split_first() {
+                    if c.span.start >= arg.span.start {
+                        break;
+                    }
+                    out.push(' ');
+                    out.push_str(&c.item.text);
+                    *comments = rest;
+                }
+                out.push(' ');
+                flat_into(arg, comments, out);
+            }
+            while let Some((c, rest)) = comments.split_first() {
+                if c.span.start >= expr.span.end {
+                    break;
+                }
+                out.push(' ');
+                out.push_str(&c.item.text);
+                *comments = rest;
+            }
+            out.push(')');
+        }
+    }
+}
+
+fn header(def: &Definition) -> String {
+    match def {
+        Definition::Value { name: i, .. } => format!("{} is", i.item.0),
+        Definition::Func { name: i, args, .. } => {
+            let mut names = vec![i.item.0.as_str()];
+            names.extend(args.iter().map(|a| a.item.0.as_str()));
+            format!("({}) is", names.join(" "))
+        }
+        Definition::Import {
+            path, alias: None, ..
+        } => format!("(import {})", quote(path)),
+        Definition::Import {
+            path,
+            alias: Some(name),
+            ..
+        } => {
+            format!("(import {} as {})", quote(path), name.item.0)
+        }
+        Definition::Export { names } => {
+            let mut words = vec!["export"];
+            words.extend(names.iter().map(|n| n.item.0.as_str()));
+            format!("({})", words.join(" "))
+        }
+    }
+}
+
+/// A Printer accumulates pretty printed code, placing comments along the way.
+#[derive(Debug)]
+struct Printer<'a> {
+    out: String,
+    /// The comments we have yet to print, in order.
+    comments: &'a [Spanned<Comment>],
+    /// Whether or not the next piece of code has to start on a new line.
+    pending_newline: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn new(comments: &'a [Spanned<Comment>]) -> Self {
+        Printer {
+            out: String::new(),
+            comments,
+            pending_newline: false,
+        }
+    }
+
+    fn line(&self) -> &str {
+        &self.out[self.out.rfind('\n').map_or(0, |i| i + 1)..]
+    }
+
+    fn column(&self) -> usize {
+        self.line().chars().count()
+    }
+
+    fn newline(&mut self, indent: usize) {
+        let trimmed = self.out.trim_end_matches(' ').len();
+        self.out.truncate(trimmed);
+        self.out.push('\n');
+        self.out.push_str(&" ".repeat(indent));
+        self.pending_newline = false;
+    }
+
+    /// flat prints an expression on a single line, if the comments inside of it allow it.
+    ///
+    /// This doesn't add the expression to our output, but does consume its comments.
+    fn flat(&mut self, expr: &Expr) -> Option<String> {
+        let count = self
+            .comments
+            .iter()
+            .take_while(|c| c.span.start < expr.span.end)
+            .count();
+        let (mut inside, rest) = self.comments.split_at(count);
+        let single_line =
+            |c: &Spanned<Comment>| c.item.text.starts_with("#|") && !c.item.text.contains('\n');
+        if !inside.iter().all(single_line) {
+            return None;
+        }
+        let mut out = String::new();
+        flat_into(expr, &mut inside, &mut out);
+        self.comments = rest;
+        Some(out)
+    }
+
+    /// comments_before prints the comments coming before a position.
+    ///
+    /// Comments that followed code on the same line stay on the line we're printing.
+    fn comments_before(&mut self, pos: usize, indent: usize) {
+        while let Some((c, rest)) = self.comments.split_first() {
+            if c.span.start >= pos {
+                break;
+            }
+            self.comments = rest;
+            if self.pending_newline || !c.item.trailing && !self.line().trim().is_empty() {
+                self.newline(indent);
+            } else if !self.line().trim().is_empty() {
+                self.out.push(' ');
+            }
+            self.out.push_str(&c.item.text);
+            self.pending_newline = true;
+        }
+    }
+
+    /// trailing_comments prints the comments before a position that follow code on the same line.
+    fn trailing_comments(&mut self, pos: usize) {
+        while let Some((c, rest)) = self.comments.split_first() {
+            if c.span.start >= pos || !c.item.trailing {
+                break;
+            }
+            self.comments = rest;
+            self.out.push(' ');
+            self.out.push_str(&c.item.text);
+            self.pending_newline = true;
+        }
+    }
+
+    /// start prepares to print some code, starting at a given position.
+    fn start(&mut self, pos: usize, indent: usize) {
+        self.comments_before(pos, indent);
+        if self.pending_newline {
+            self.newline(indent);
+        }
+    }
+
+    /// expr prints an expression, going to a given indentation if it needs a new line.
+    ///
+    /// Calls which don't fit on one line keep their first argument next to the function,
+    /// with the remaining arguments on their own lines, a bit further than the call.
+    fn expr(&mut self, expr: &Expr, indent: usize) {
+        self.start(expr.span.start, indent);
+        let column = self.column();
+        let saved = self.comments;
+        let (ident, args) = match (&expr.item, self.flat(expr)) {
+            (ExprKind::Call { name: i, args }, out)
+                if !args.is_empty()
+                    && out.as_ref().is_none_or(|out| column + out.len() > WIDTH) =>
+            {
+                self.comments = saved;
+                (i, args)
+            }
+            // Only calls can have comments inside of them, so nothing else fails to be flat.
+            (_, out) => {
+                self.out.push_str(&out.unwrap_or_default());
+                return;
+            }
+        };
+        self.out.push_str(&format!("({} ", ident.item.0));
+        let first_column = self.column();
+        self.expr(&args[0], first_column);
+        for arg in &args[1..] {
+            self.trailing_comments(arg.span.start);
+            self.newline(column + INDENT);
+            self.expr(arg, column + INDENT);
+        }
+        self.start(expr.span.end, column + INDENT);
+        self.out.push(')');
+    }
+}
+
+/// expr pretty prints an expression, along with the comments inside of it.
+pub fn expr(expr: &Expr, comments: &[Spanned<Comment>]) -> String {
+    let mut printer = Printer::new(comments);
+    printer.expr(expr, 0);
+    printer.comments_before(usize::MAX, 0);
+    printer.out
+}
+
+/// definition pretty prints a definition, on a single line if it fits in a given width.
+///
+/// Otherwise, this starts with a line containing everything up to `is`, with the
+/// body following on the next lines. Comments before the body go above that first
+/// line, unless they were already next to it.
+pub fn definition(def: &Definition, comments: &[Spanned<Comment>], width: usize) -> String {
+    let header = header(def);
+    let body = match def {
+        Definition::Value { body: e, .. } | Definition::Func { body: e, .. } => e,
+        // Imports and exports are nothing but a header, so only their comments can be moved around.
+        Definition::Import { .. } | Definition::Export { .. } => {
+            let mut printer = Printer::new(comments);
+            printer.out.push_str(&header);
+            printer.comments_before(usize::MAX, 0);
+            return printer.out;
+        }
+    };
+    let count = comments
+        .iter()
+        .take_while(|c| c.span.start < body.span.start)
+        .count();
+    let (before, rest) = comments.split_at(count);
+    let mut printer = Printer::new(rest);
+    for c in before.iter().filter(|c| !c.item.trailing) {
+        printer.out.push_str(&c.item.text);
+        printer.newline(0);
+    }
+    printer.out.push_str(&header);
+    for c in before.iter().filter(|c| c.item.trailing) {
+        printer.out.push(' ');
+        printer.out.push_str(&c.item.text);
+        printer.pending_newline = true;
+    }
+    let saved = printer.comments;
+    match printer.flat(body) {
+        Some(flat) if !printer.pending_newline && header.len() + 1 + flat.len() <= width => {
+            printer.out.push(' ');
+            printer.out.push_str(&flat);
+        }
+        _ => {
+            printer.comments = saved;
+            printer.newline(INDENT);
+            printer.expr(body, INDENT);
+        }
+    }
+    printer.comments_before(usize::MAX, 0);
+    printer.out
+}
@@ -2,18 +2,28 @@ use std::mem;
 use std::str::Chars;
 
 use peekmore::{PeekMore, PeekMoreIterator};
+use serde::Serialize;
+
+use crate::span::{Span, Spanned};
 
 /// Represents a single token produced by our lexer
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value")]
 pub enum Token {
-    /// <!--
+    /// `<!--`
     CommentOpen,
-    /// -->
+    /// `-->`
     CommentClose,
-    /// `
+    /// ``
     Tick,
     /// The \n character
     Newline,
+    /// Text escaped with a backslash, which is output without it, like ``` \`` ``` for ``` `` ```.
+    Escaped(String),
+    /// A line of three or more backticks or tildes, opening or closing a fenced code block.
+    ///
+    /// This contains the whole line, without the \n character.
+    Fence(String),
     /// Everything else is just raw text.
     Raw(String),
 }
@@ -24,11 +34,67 @@ impl Token {
         match self {
             Token::CommentOpen => acc.push_str("<!--"),
             Token::CommentClose => acc.push_str("-->"),
-            Token::Tick => acc.push('`'),
+            Token::Tick => acc.push_str("``"),
             Token::Newline => acc.push('\n'),
-            Token::Raw(s) => acc.push_str(s),
+            Token::Escaped(s) | Token::Fence(s) | Token::Raw(s) => acc.push_str(s),
+        }
+    }
+}
+
+/// fence_marker finds the character and length of the fence starting a line, if there's one.
+///
+/// This follows CommonMark: up to 3 spaces of indentation, then at least 3 backticks or tildes.
+/// Backtick fences can't have backticks after them.
+fn fence_marker(line: &str) -> Option<(char, usize)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let line = &line[indent..];
+    let c = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.len() - line.trim_start_matches(c).len();
+    if len < 3 || (c == '`' && line[len..].contains('`')) {
+        return None;
+    }
+    Some((c, len))
+}
+
+/// fence_info finds the info string of a fence line, like `rust` in ```` ```rust ````.
+pub fn fence_info(fence: &str) -> &str {
+    fence
+        .trim_start_matches(' ')
+        .trim_start_matches(['`', '~'])
+        .trim()
+}
+
+/// indentation measures the indentation of a line in columns, with tabs going to multiples of 4.
+fn indentation(line: &str) -> usize {
+    let mut columns = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => columns += 1,
+            '\t' => columns += 4 - columns % 4,
+            _ => break,
         }
     }
+    columns
+}
+
+/// list_item checks whether a line starts an item of a list, like `- item` or `1. item`.
+fn list_item(line: &str) -> bool {
+    let line = line.trim_start();
+    let marker = match line.chars().next() {
+        Some('-' | '*' | '+') => 1,
+        Some('0'..='9') => {
+            let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            match line[digits..].chars().next() {
+                Some('.' | ')') => digits + 1,
+                _ => return false,
+            }
+        }
+        _ => return false,
+    };
+    line[marker..].is_empty() || line[marker..].starts_with([' ', '\t'])
 }
 
 /// A Lexer uses our source code to emit tokens.
@@ -41,7 +107,21 @@ pub struct Lexer<'a> {
     /// Used to a accumulate a raw string token
     raw_acc: String,
     /// This may contain a buffered output token
-    produced: Option<Token>,
+    produced: Option<Spanned<Token>>,
+    /// Whether we're at the start of a line.
+    line_start: bool,
+    /// The character and length of the fence of the code block we're in, if any.
+    fence: Option<(char, usize)>,
+    /// Whether we're in an indented code block.
+    indented: bool,
+    /// Whether the last line was blank, which indented code blocks need before them.
+    blank: bool,
+    /// Whether we're in a list, where indented lines continue items instead of being code.
+    list: bool,
+    /// Whether we're inside of a comment, where Markdown doesn't apply.
+    comment: bool,
+    /// Whether we're inside of an interpolation, where Markdown doesn't apply either.
+    interpolation: bool,
 }
 
 impl<'a> Lexer<'a> {
@@ -52,20 +132,178 @@ impl<'a> Lexer<'a> {
             pos: 0,
             raw_acc: String::new(),
             produced: None,
+            line_start: true,
+            fence: None,
+            indented: false,
+            blank: true,
+            list: false,
+            comment: false,
+            interpolation: false,
+        }
+    }
+
+    /// bump advances to the next character in our source code.
+    fn bump(&mut self) -> Option<char> {
+        let c = self.src.next()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    /// peek_line looks at the rest of the current line, without advancing.
+    fn peek_line(&mut self) -> String {
+        let mut line = String::new();
+        let mut i = 0;
+        while let Some(c) = self.src.peek_nth(i) {
+            if *c == '\n' {
+                break;
+            }
+            line.push(*c);
+            i += 1;
+        }
+        line
+    }
+
+    /// fence lexes the line starting here, if it opens or closes a fenced code block.
+    ///
+    /// A block is only closed by a fence using the same character, at least as long as
+    /// the one opening it, with nothing after it.
+    fn fence(&mut self) -> Option<Token> {
+        let line = self.peek_line();
+        let (c, len) = fence_marker(&line)?;
+        match self.fence {
+            None => self.fence = Some((c, len)),
+            Some((open_c, open_len)) => {
+                if c != open_c || len < open_len || !fence_info(&line).is_empty() {
+                    return None;
+                }
+                self.fence = None;
+            }
+        }
+        for _ in line.chars() {
+            self.bump();
+        }
+        Some(Token::Fence(line))
+    }
+
+    /// take_line takes the rest of the current line as raw text.
+    fn take_line(&mut self, line: &str) {
+        for _ in line.chars() {
+            self.bump();
+        }
+        self.raw_acc.push_str(line);
+    }
+
+    /// line lexes the start of a line, which might be a fence, or the inside of a code block.
+    ///
+    /// Code blocks are taken as raw text, so that the syntax inside of them does nothing.
+    fn line(&mut self) -> Option<Token> {
+        if self.comment || self.interpolation {
+            return None;
+        }
+        let line = self.peek_line();
+        if let Some(tok) = self.fence() {
+            self.indented = false;
+            self.blank = false;
+            return Some(tok);
+        }
+        if self.fence.is_some() {
+            self.take_line(&line);
+            return None;
+        }
+        let blank = line.trim().is_empty();
+        if !blank {
+            let indent = indentation(&line);
+            if indent >= 4 && (self.indented || (self.blank && !self.list)) {
+                self.indented = true;
+                self.take_line(&line);
+            } else {
+                self.indented = false;
+                if list_item(&line) {
+                    self.list = true;
+                } else if indent == 0 {
+                    self.list = false;
+                }
+            }
+        }
+        self.blank = blank;
+        None
+    }
+
+    /// raw_comment takes a comment as raw text, after the `<!---` opening it.
+    ///
+    /// Like other comments, it runs until the next `-->`, or the end of the document.
+    fn raw_comment(&mut self) {
+        self.raw_acc.push_str("<!---");
+        while let Some(c) = self.bump() {
+            self.raw_acc.push(c);
+            if self.raw_acc.ends_with("-->") {
+                return;
+            }
+        }
+    }
+
+    /// code_span lexes a code span, after the run of backticks opening it.
+    ///
+    /// Like in Markdown, the span closes at the next run of as many backticks, without
+    /// going past the end of a paragraph. If there's none, the backticks are just text.
+    fn code_span(&mut self, ticks: usize) {
+        self.raw_acc.push_str(&"`".repeat(ticks));
+        let mut run = 0;
+        let mut i = 0;
+        let mut blank = false;
+        loop {
+            match self.src.peek_nth(i).copied() {
+                Some('`') => {
+                    run += 1;
+                    blank = false;
+                }
+                next => {
+                    if run == ticks {
+                        break;
+                    }
+                    run = 0;
+                    match next {
+                        None => return,
+                        Some('\n') if blank => return,
+                        Some('\n') => blank = true,
+                        Some(c) if c.is_whitespace() => {}
+                        Some(_) => blank = false,
+                    }
+                }
+            }
+            i += 1;
+        }
+        for _ in 0..i {
+            let c = self.bump().unwrap();
+            self.raw_acc.push(c);
         }
     }
 
-    fn take_raw(&mut self) -> Option<Token> {
+    /// take_raw produces the raw string we've accumulated, which ends at a given position.
+    fn take_raw(&mut self, end: usize) -> Option<Spanned<Token>> {
         if self.raw_acc.is_empty() {
             None
         } else {
-            Some(Token::Raw(mem::take(&mut self.raw_acc)))
+            let span = Span::new(end - self.raw_acc.len(), end);
+            Some(Spanned::new(Token::Raw(mem::take(&mut self.raw_acc)), span))
+        }
+    }
+
+    /// produce emits a token starting at a given position, after the raw string before it.
+    fn produce(&mut self, tok: Token, start: usize) -> Spanned<Token> {
+        let tok = Spanned::new(tok, Span::new(start, self.pos));
+        match self.take_raw(start) {
+            None => tok,
+            Some(raw) => {
+                self.produced = Some(tok);
+                raw
+            }
         }
     }
 }
 
 impl<'a> Iterator for Lexer<'a> {
-    type Item = Token;
+    type Item = Spanned<Token>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(tok) = mem::take(&mut self.produced) {
@@ -73,45 +311,89 @@ impl<'a> Iterator for Lexer<'a> {
         }
 
         loop {
-            let next = match self.src.next() {
-                None => return self.take_raw(),
+            let start = self.pos;
+            if mem::take(&mut self.line_start) {
+                if let Some(tok) = self.line() {
+                    return Some(self.produce(tok, start));
+                }
+                if self.pos != start {
+                    continue;
+                }
+            }
+            let next = match self.bump() {
+                None => return self.take_raw(start),
                 Some(c) => c,
             };
 
             let produced = match next {
-                '\n' => Some(Token::Newline),
-                '`' => if Some('`').as_ref() == self.src.peek() {
-                    self.src.next();
-                    if Some('`').as_ref() == self.src.peek() {
-                        self.src.next();
-                        self.raw_acc.push_str("``");
-                        None
-                    } else {
+                '\n' => {
+                    self.line_start = true;
+                    Some(Token::Newline)
+                }
+                '`' => {
+                    let mut ticks = 1;
+                    while self.src.peek() == Some(&'`') {
+                        self.bump();
+                        ticks += 1;
+                    }
+                    if ticks == 2 {
+                        if !self.comment {
+                            self.interpolation = !self.interpolation;
+                        }
                         Some(Token::Tick)
+                    } else if self.comment || self.interpolation {
+                        // Code can have ticks of its own, like in strings.
+                        self.raw_acc.push_str(&"`".repeat(ticks));
+                        continue;
+                    } else {
+                        self.code_span(ticks);
+                        continue;
                     }
-                } else {
-                    None
                 }
-                '<' => {
-                    if self.src.peek_nth(0).map_or(false, |x| *x == '!')
-                        && self.src.peek_nth(1).map_or(false, |x| *x == '-')
-                        && self.src.peek_nth(2).map_or(false, |x| *x == '-')
+                // Escaped double ticks stand for themselves.
+                '\\' if self.src.peek_nth(0) == Some(&'`')
+                    && self.src.peek_nth(1) == Some(&'`') =>
+                {
+                    self.bump();
+                    self.bump();
+                    Some(Token::Escaped("``".to_string()))
+                }
+                // Escaped punctuation is just text, like in Markdown.
+                '\\' if self.src.peek().is_some_and(char::is_ascii_punctuation) => {
+                    let escaped = self.bump().unwrap();
+                    self.raw_acc.push(next);
+                    self.raw_acc.push(escaped);
+                    continue;
+                }
+                // Code in interpolations can have `<!--` of its own, like in strings.
+                '<' if !self.interpolation => {
+                    if self.src.peek_nth(0).is_some_and(|x| *x == '!')
+                        && self.src.peek_nth(1).is_some_and(|x| *x == '-')
+                        && self.src.peek_nth(2).is_some_and(|x| *x == '-')
                     {
-                        self.src.next();
-                        self.src.next();
-                        self.src.next();
+                        self.bump();
+                        self.bump();
+                        self.bump();
+                        // Comments with a third dash are copied as they are.
+                        if self.src.peek() == Some(&'-') {
+                            self.bump();
+                            self.raw_comment();
+                            continue;
+                        }
+                        self.comment = true;
 
-                        return Some(Token::CommentOpen);
+                        Some(Token::CommentOpen)
                     } else {
                         None
                     }
                 }
-                '-' => {
-                    if self.src.peek_nth(0).map_or(false, |x| *x == '-')
-                        && self.src.peek_nth(1).map_or(false, |x| *x == '>')
+                '-' if self.comment => {
+                    if self.src.peek_nth(0).is_some_and(|x| *x == '-')
+                        && self.src.peek_nth(1).is_some_and(|x| *x == '>')
                     {
-                        self.src.next();
-                        self.src.next();
+                        self.bump();
+                        self.bump();
+                        self.comment = false;
 
                         Some(Token::CommentClose)
                     } else {
@@ -121,14 +403,139 @@ impl<'a> Iterator for Lexer<'a> {
                 _ => None,
             };
             if let Some(tok) = produced {
-                if let Some(raw) = self.take_raw() {
-                    self.produced = Some(tok);
-                    return Some(raw);
-                } else {
-                    return Some(tok);
-                }
+                return Some(self.produce(tok, start));
             }
             self.raw_acc.push(next);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(src: &str) -> Vec<Token> {
+        Lexer::new(src).map(|tok| tok.item).collect()
+    }
+
+    fn raw(s: &str) -> Token {
+        Token::Raw(s.to_string())
+    }
+
+    #[test]
+    fn double_ticks_interpolate() {
+        assert_eq!(
+            tokens("a ``x`` b"),
+            vec![raw("a "), Token::Tick, raw("x"), Token::Tick, raw(" b")]
+        );
+    }
+
+    #[test]
+    fn code_spans_are_raw() {
+        assert_eq!(
+            tokens("a `` ` `` b"),
+            vec![raw("a "), Token::Tick, raw(" ` "), Token::Tick, raw(" b")]
+        );
+        assert_eq!(tokens("a ```x``y``` b"), vec![raw("a ```x``y``` b")]);
+        assert_eq!(tokens("a `<!--` b"), vec![raw("a `<!--` b")]);
+    }
+
+    #[test]
+    fn code_spans_stop_at_paragraphs() {
+        assert_eq!(
+            tokens("a `b\n\n``x``"),
+            vec![
+                raw("a `b"),
+                Token::Newline,
+                Token::Newline,
+                Token::Tick,
+                raw("x"),
+                Token::Tick,
+            ]
+        );
+    }
+
+    #[test]
+    fn code_blocks_are_raw() {
+        assert_eq!(
+            tokens("```\n``x``\n```\n"),
+            vec![
+                Token::Fence("```".to_string()),
+                Token::Newline,
+                raw("``x``"),
+                Token::Newline,
+                Token::Fence("```".to_string()),
+                Token::Newline,
+            ]
+        );
+        assert_eq!(
+            tokens("a\n\n    ``x``\n"),
+            vec![
+                raw("a"),
+                Token::Newline,
+                Token::Newline,
+                raw("    ``x``"),
+                Token::Newline,
+            ]
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            tokens("\\``x\\`` \\*"),
+            vec![
+                Token::Escaped("``".to_string()),
+                raw("x"),
+                Token::Escaped("``".to_string()),
+                raw(" \\*"),
+            ]
+        );
+    }
+
+    #[test]
+    fn raw_comments() {
+        assert_eq!(tokens("<!--- ``x`` -->"), vec![raw("<!--- ``x`` -->")]);
+    }
+
+    #[test]
+    fn ticks_in_comments_are_code() {
+        assert_eq!(
+            tokens("<!-- x is \"it`s\" --> and `y`."),
+            vec![
+                Token::CommentOpen,
+                raw(" x is \"it`s\" "),
+                Token::CommentClose,
+                raw(" and `y`."),
+            ]
+        );
+    }
+
+    #[test]
+    fn ticks_in_interpolations_are_code() {
+        assert_eq!(
+            tokens("``\"a`b\"`` and `y`."),
+            vec![Token::Tick, raw("\"a`b\""), Token::Tick, raw(" and `y`."),]
+        );
+    }
+
+    #[test]
+    fn comments_in_interpolations_are_code() {
+        assert_eq!(
+            tokens("A ``\"<!--\"`` B\n```\n(+ 1 1)\n```"),
+            vec![
+                raw("A "),
+                Token::Tick,
+                raw("\"<!--\""),
+                Token::Tick,
+                raw(" B"),
+                Token::Newline,
+                Token::Fence("```".to_string()),
+                Token::Newline,
+                raw("(+ 1 1)"),
+                Token::Newline,
+                Token::Fence("```".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,385 @@
+//! Wahlbergdown is a programmable version of Markdown.
+//!
+//! Documents contain code inside of comments, like `<!-- x is 42 -->`, which define
+//! values and functions, and code between double ticks, which gets replaced by its value,
+//! escaped so that it shows up as text, unless it's Markdown made by functions like `bold`.
+//! Fenced code blocks tagged `wahl` can hold both, and get replaced by the values of their
+//! expressions. Other code spans and code blocks are left alone, like in Markdown.
+//!
+//! The simplest way to use this crate is [`render`]:
+//!
+//! ```
+//! let output = wahlbergdown::render("<!-- x is 40 -->``(+ x 2)``").unwrap();
+//! assert_eq!(output, "42");
+//! ```
+//!
+//! To keep definitions around between documents, or to run code directly,
+//! use a [`Renderer`], or an [`Interpreter`].
+//!
+//! # Stability
+//!
+//! The items at the root of this crate are the stable API: [`render`], [`Renderer`],
+//! [`Rendered`], [`KeepDefinitions`], [`Interpreter`], [`Limits`], [`Value`], [`Error`],
+//! and [`Errors`], along with [`Span`] and [`Code`], which they use. So is the [`html`]
+//! module, which renders Markdown to HTML. These follow semantic versioning.
+//! [`Value`] is `#[non_exhaustive]`, since new kinds of values can come in minor releases.
+//!
+//! The modules themselves are public for the `wahlbergdown` command line tool,
+//! and for tooling that wants to look at the syntax of documents. Anything only
+//! reachable through them may change in any release.
+pub mod error;
+pub mod html;
+pub mod interpreter;
+pub mod lexer;
+pub mod parser;
+pub mod span;
+
+pub use error::{Error, Errors};
+pub use interpreter::{Interpreter, Limits, Value};
+pub use parser::Code;
+pub use span::Span;
+
+use std::path::Path;
+use std::str::FromStr;
+use std::{fs, io};
+
+use interpreter::markdown::{self, Context};
+use lexer::Lexer;
+use parser::{DocumentChunk, Parser};
+use span::Spanned;
+
+/// The result of rendering a document.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rendered {
+    /// The produced markdown, with errors inlined.
+    pub output: String,
+    /// The errors we ran into while rendering.
+    pub errors: Vec<Error>,
+}
+
+impl Rendered {
+    /// into_result returns the output, unless there were errors.
+    pub fn into_result(self) -> Result<String, Errors> {
+        if self.errors.is_empty() {
+            Ok(self.output)
+        } else {
+            Err(Errors(self.errors))
+        }
+    }
+}
+
+/// KeepDefinitions says what happens to the comments defining things when rendering.
+///
+/// Blocks tagged `wahl` are replaced by their output whatever this is, so the definitions
+/// in them are always removed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeepDefinitions {
+    /// Remove them from the output.
+    #[default]
+    Strip,
+    /// Keep them as they are, as comments.
+    Comment,
+    /// Show their code in fenced code blocks.
+    Fence,
+}
+
+impl FromStr for KeepDefinitions {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strip" => Ok(KeepDefinitions::Strip),
+            "comment" => Ok(KeepDefinitions::Comment),
+            "fence" => Ok(KeepDefinitions::Fence),
+            _ => Err(format!(
+                "unknown mode `{}`, expected `strip`, `comment`, or `fence`",
+                s
+            )),
+        }
+    }
+}
+
+/// A Renderer renders documents, using an interpreter that lives across documents.
+///
+/// Values and functions defined in one document are visible in the documents
+/// rendered after it.
+#[derive(Clone, Debug, Default)]
+pub struct Renderer {
+    interpreter: Interpreter,
+    keep_definitions: KeepDefinitions,
+}
+
+impl Renderer {
+    pub fn new() -> Self {
+        Self::with_interpreter(Interpreter::new())
+    }
+
+    /// Create a renderer using an existing interpreter, with its definitions.
+    pub fn with_interpreter(interpreter: Interpreter) -> Self {
+        Renderer {
+            interpreter,
+            keep_definitions: KeepDefinitions::default(),
+        }
+    }
+
+    /// set_keep_definitions changes what happens to the comments defining things.
+    ///
+    /// By default, they're stripped from the output.
+    pub fn set_keep_definitions(&mut self, keep: KeepDefinitions) {
+        self.keep_definitions = keep;
+    }
+
+    /// interpreter gives access to the interpreter used to render documents.
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+
+    /// render runs the code in a document, producing markdown.
+    ///
+    /// Errors don't stop rendering: they get inlined in the output, and collected.
+    /// The exception is the output getting larger than the interpreter's limit, which
+    /// stops rendering there.
+    pub fn render(&mut self, src: &str) -> Rendered {
+        let chunks: Vec<_> = Parser::new(Lexer::new(src)).collect();
+        let mut rendered = Rendered {
+            output: String::new(),
+            errors: Vec::new(),
+        };
+        let started = self.interpreter.start_budget();
+        self.render_chunks(&chunks, &mut rendered);
+        self.interpreter.end_budget(started);
+        rendered
+    }
+
+    /// render_file renders the document in a file.
+    ///
+    /// The files it imports and includes are found relative to it.
+    pub fn render_file(&mut self, path: &Path) -> io::Result<Rendered> {
+        let src = fs::read_to_string(path)?;
+        self.interpreter.set_file(path);
+        Ok(self.render(&src))
+    }
+
+    /// render_chunks renders chunks of a document, returning false if rendering has to stop.
+    fn render_chunks(
+        &mut self,
+        chunks: &[Spanned<DocumentChunk>],
+        rendered: &mut Rendered,
+    ) -> bool {
+        let max_output = self.interpreter.limits().max_output;
+        for (i, chunk) in chunks.iter().enumerate() {
+            let result = match &chunk.item {
+                DocumentChunk::Raw(r) => {
+                    rendered.output.push_str(r);
+                    Ok(true)
+                }
+                DocumentChunk::Comment(c) => match self.interpreter.definition(c.clone()) {
+                    Err(e) => Err((format!("<!--ERROR: {}-->", e), e)),
+                    Ok(()) => {
+                        let line_end = match chunks.get(i + 1).map(|c| &c.item) {
+                            None => true,
+                            Some(DocumentChunk::Raw(r)) => r.starts_with('\n'),
+                            Some(_) => false,
+                        };
+                        self.keep_definition(c, line_end, rendered);
+                        Ok(true)
+                    }
+                },
+                DocumentChunk::Interpolate(c) => self
+                    .interpolate(c, context(chunks, i), rendered)
+                    .map_err(|e| (format!("`ERROR: {}`", e), e)),
+                DocumentChunk::Block { code, source, show } => {
+                    if *show {
+                        rendered.output.push_str(source);
+                    }
+                    self.block(code, *show, rendered)
+                        .map(|()| true)
+                        .map_err(|e| (format!("`ERROR: {}`", e), e))
+                }
+            };
+            match result {
+                Err((inlined, e)) => {
+                    rendered.output.push_str(&inlined);
+                    rendered.errors.push(e);
+                }
+                Ok(false) => return false,
+                Ok(true) => {}
+            }
+            if let Some(max_output) = max_output {
+                if rendered.output.len() > max_output {
+                    let mut end = max_output;
+                    while !rendered.output.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    rendered.output.truncate(end);
+                    let message = format!("output is larger than {} bytes", max_output);
+                    rendered.errors.push(Error::new(message, chunk.span));
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// keep_definition renders the comment defining something, unless definitions are stripped.
+    ///
+    /// line_end tells whether the comment is followed by the end of its line.
+    fn keep_definition(&self, code: &Code, line_end: bool, rendered: &mut Rendered) {
+        match self.keep_definitions {
+            KeepDefinitions::Strip => {}
+            KeepDefinitions::Comment => {
+                rendered.output.push_str("<!--");
+                rendered.output.push_str(&code.src);
+                rendered.output.push_str("-->");
+            }
+            KeepDefinitions::Fence => {
+                // Fences have to start their own line.
+                if !rendered.output.is_empty() && !rendered.output.ends_with('\n') {
+                    rendered.output.push('\n');
+                }
+                rendered.output.push_str("```\n");
+                rendered.output.push_str(code.src.trim());
+                rendered.output.push_str("\n```");
+                // Closing fences have to end their line, too.
+                if !line_end {
+                    rendered.output.push('\n');
+                }
+            }
+        }
+    }
+
+    /// block runs a block of code, rendering the values of its expressions, one per line.
+    ///
+    /// When the block is shown, its output is separated from it by an empty line.
+    fn block(&mut self, code: &Code, show: bool, rendered: &mut Rendered) -> Result<(), Error> {
+        let mut separator = if show { "\n\n" } else { "" };
+        let statements = match interpreter::parse_block(code) {
+            Err(e) => {
+                rendered.output.push_str(separator);
+                return Err(e);
+            }
+            Ok(statements) => statements,
+        };
+        for statement in statements {
+            // Errors go on their own line, like values.
+            let value = match self.interpreter.statement(statement) {
+                Ok(None) => continue,
+                value => value,
+            };
+            rendered.output.push_str(separator);
+            separator = "\n";
+            if let Some(value) = value? {
+                let value = markdown::render(&value, Context::Paragraph);
+                rendered.output.push_str(&value);
+            }
+        }
+        Ok(())
+    }
+
+    /// interpolate renders the value of an expression, or the file it includes.
+    ///
+    /// The value is escaped for the context it ends up in, unless it's Markdown already.
+    /// Like render_chunks, this returns false if rendering has to stop.
+    fn interpolate(
+        &mut self,
+        code: &Code,
+        context: Context,
+        rendered: &mut Rendered,
+    ) -> Result<bool, Error> {
+        let expr = interpreter::parse_expr(code)?;
+        let path = match interpreter::included(&expr) {
+            None => {
+                let value = self.interpreter.eval(expr)?;
+                rendered.output.push_str(&markdown::render(&value, context));
+                return Ok(true);
+            }
+            Some(path) => path,
+        };
+        let (file, chunks) = self.interpreter.load_file(path, expr.span)?;
+        self.interpreter.enter_file(file);
+        let keep_going = self.render_chunks(&chunks, rendered);
+        self.interpreter.exit_file();
+        Ok(keep_going)
+    }
+}
+
+/// context finds where an interpolated value ends up, from the text around it on its line.
+///
+/// Lines with pipes are taken to be rows of tables.
+fn context(chunks: &[Spanned<DocumentChunk>], i: usize) -> Context {
+    // Blocks are lines of their own.
+    fn raw(chunk: &Spanned<DocumentChunk>) -> Option<&str> {
+        match &chunk.item {
+            DocumentChunk::Raw(r) => Some(r),
+            DocumentChunk::Block { .. } => Some("\n"),
+            _ => None,
+        }
+    }
+    let mut line = String::new();
+    for r in chunks[..i].iter().rev().filter_map(raw) {
+        match r.rfind('\n') {
+            Some(newline) => {
+                line.push_str(&r[newline..]);
+                break;
+            }
+            None => line.push_str(r),
+        }
+    }
+    for r in chunks[i + 1..].iter().filter_map(raw) {
+        match r.find('\n') {
+            Some(newline) => {
+                line.push_str(&r[..newline]);
+                break;
+            }
+            None => line.push_str(r),
+        }
+    }
+    if line.contains('|') {
+        Context::Cell
+    } else {
+        Context::Paragraph
+    }
+}
+
+/// render runs the code in a document, producing markdown, or every error that happened.
+pub fn render(src: &str) -> Result<String, Errors> {
+    Renderer::new().render(src).into_result()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_keeping(src: &str, keep: KeepDefinitions) -> String {
+        let mut renderer = Renderer::new();
+        renderer.set_keep_definitions(keep);
+        renderer.render(src).into_result().unwrap()
+    }
+
+    #[test]
+    fn keep_definitions() {
+        let src = "Intro <!-- x is 1 --> and ``x``.";
+        assert_eq!(render_keeping(src, KeepDefinitions::Strip), "Intro  and 1.");
+        assert_eq!(
+            render_keeping(src, KeepDefinitions::Comment),
+            "Intro <!-- x is 1 --> and 1."
+        );
+        assert_eq!(
+            render_keeping(src, KeepDefinitions::Fence),
+            "Intro \n```\nx is 1\n```\n and 1."
+        );
+        assert_eq!(
+            render_keeping("<!-- x is 1 -->\n``x``", KeepDefinitions::Fence),
+            "```\nx is 1\n```\n1"
+        );
+    }
+
+    #[test]
+    fn kept_fences_close() {
+        let markdown = render_keeping("Intro <!-- x is 1 --> and ``x``.", KeepDefinitions::Fence);
+        assert_eq!(
+            html::render(&markdown),
+            "<p>Intro</p>\n<pre><code>x is 1\n</code></pre>\n<p>and 1.</p>\n"
+        );
+    }
+}
@@ -1,16 +1,24 @@
-mod interpreter;
-mod lexer;
-mod parser;
+mod data;
+mod dump;
+mod fmt;
+mod publish;
+mod repl;
+mod watch;
 
 use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
+use std::process;
 
-use lexer::Lexer;
-use parser::Parser;
 use structopt::StructOpt;
+use wahlbergdown::interpreter::ExprKind;
+use wahlbergdown::lexer::Lexer;
+use wahlbergdown::parser::Parser;
+use wahlbergdown::span::Spanned;
+use wahlbergdown::{interpreter, span, Error, Interpreter, KeepDefinitions, Renderer, Value};
 
-use crate::parser::DocumentChunk;
+use crate::dump::Format;
+use crate::publish::{Publisher, To};
 
 /// A command that our CLI can process
 #[derive(Debug, StructOpt)]
@@ -20,63 +28,295 @@ enum Command {
         /// The file containing Wahlbergdown code you want to lex.
         #[structopt(name = "INPUT_FILE", parse(from_os_str))]
         input_file: PathBuf,
+        /// The output format: `text` or `json`.
+        #[structopt(long, default_value = "text")]
+        format: Format,
     },
     /// Print the AST produced by the parser.
     Parse {
         /// The file containing Wahlbergdown code you want to parse.
         #[structopt(name = "INPUT_FILE", parse(from_os_str))]
         input_file: PathBuf,
+        /// The output format: `text` or `json`.
+        ///
+        /// The JSON output always contains the code parsed inside of each chunk.
+        #[structopt(long, default_value = "text")]
+        format: Format,
+        /// Also print the code parsed inside of each chunk.
+        #[structopt(long)]
+        deep: bool,
     },
     // Run a file.
     Run {
         /// The file containing Wahlbergdown code you want to run.
         #[structopt(name = "INPUT_FILE", parse(from_os_str))]
         input_file: PathBuf,
+        #[structopt(flatten)]
+        defines: Defines,
+        /// What to do with the comments defining things: `strip`, `comment`, or `fence`.
+        ///
+        /// `comment` keeps them as they are, and `fence` shows their code in fenced code blocks.
+        /// Definitions in `wahl` blocks are always removed, along with the rest of the block.
+        #[structopt(long, default_value = "strip")]
+        keep_definitions: KeepDefinitions,
+        /// The format of the output: `markdown` or `html`.
+        #[structopt(long, default_value = "markdown")]
+        to: To,
+        /// The page template to put HTML into, replacing `{{content}}`.
+        ///
+        /// Only used with `--to html`.
+        #[structopt(long, parse(from_os_str))]
+        template: Option<PathBuf>,
+        /// Don't define the functions of the prelude, like `sum` and `max`.
+        #[structopt(long)]
+        no_prelude: bool,
     },
+    /// Check files for problems, without running them.
+    Check {
+        /// The files containing Wahlbergdown code you want to check.
+        #[structopt(name = "INPUT_FILES", parse(from_os_str), required = true)]
+        input_files: Vec<PathBuf>,
+        #[structopt(flatten)]
+        defines: Defines,
+        /// Don't define the functions of the prelude, like `sum` and `max`.
+        #[structopt(long)]
+        no_prelude: bool,
+    },
+    /// Format the code in files, leaving the rest of the files untouched.
+    Fmt {
+        /// The files containing Wahlbergdown code you want to format.
+        #[structopt(name = "INPUT_FILES", parse(from_os_str), required = true)]
+        input_files: Vec<PathBuf>,
+        /// Only check that the files are formatted, instead of changing them.
+        #[structopt(long)]
+        check: bool,
+    },
+    /// Evaluate a single expression, and print its value.
+    Eval {
+        /// The expression to evaluate.
+        #[structopt(name = "EXPR")]
+        expr: String,
+        /// Run the definitions in this file first.
+        #[structopt(long, parse(from_os_str))]
+        load: Vec<PathBuf>,
+        /// Don't define the functions of the prelude, like `sum` and `max`.
+        #[structopt(long)]
+        no_prelude: bool,
+    },
+    /// Start an interactive session.
+    Repl {
+        /// Don't define the functions of the prelude, like `sum` and `max`.
+        #[structopt(long)]
+        no_prelude: bool,
+    },
+    /// Run a file again each time it changes.
+    Watch {
+        /// The file containing Wahlbergdown code you want to watch.
+        #[structopt(name = "INPUT_FILE", parse(from_os_str))]
+        input_file: PathBuf,
+        /// Write the output to this file, instead of printing it.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+        #[structopt(flatten)]
+        defines: Defines,
+        /// What to do with the comments defining things: `strip`, `comment`, or `fence`.
+        ///
+        /// `comment` keeps them as they are, and `fence` shows their code in fenced code blocks.
+        /// Definitions in `wahl` blocks are always removed, along with the rest of the block.
+        #[structopt(long, default_value = "strip")]
+        keep_definitions: KeepDefinitions,
+        /// The format of the output: `markdown` or `html`.
+        #[structopt(long, default_value = "markdown")]
+        to: To,
+        /// The page template to put HTML into, replacing `{{content}}`.
+        ///
+        /// Only used with `--to html`.
+        #[structopt(long, parse(from_os_str))]
+        template: Option<PathBuf>,
+        /// Don't define the functions of the prelude, like `sum` and `max`.
+        #[structopt(long)]
+        no_prelude: bool,
+    },
+}
+
+/// Defines are the values given on the command line, to define before running a file.
+#[derive(Debug, StructOpt)]
+struct Defines {
+    /// Define a value before running, like `--define name="Ada"` or `--define year=2024`.
+    #[structopt(
+        long = "define",
+        value_name = "NAME=VALUE",
+        number_of_values = 1,
+        parse(try_from_str = parse_define)
+    )]
+    values: Vec<(String, Value)>,
+    /// Define the top-level keys of a JSON, TOML, or YAML file as values.
+    ///
+    /// Values given with `--define` take precedence.
+    #[structopt(long, number_of_values = 1, parse(from_os_str))]
+    data: Vec<PathBuf>,
+}
+
+impl Defines {
+    /// define defines the values in an interpreter, reading the data files again.
+    fn define(&self, interpreter: &mut Interpreter) -> Result<(), String> {
+        for data_file in &self.data {
+            let values = data::load(data_file)
+                .map_err(|e| format!("{}: ERROR: {}", data_file.display(), e))?;
+            for (name, value) in values {
+                interpreter.define(&name, value);
+            }
+        }
+        for (name, value) in &self.values {
+            interpreter.define(name, value.clone());
+        }
+        Ok(())
+    }
 }
 
-fn lex_and_stop(input_file: &Path) {
-    let src = fs::read_to_string(input_file).expect("failed to read input file");
-    let tokens = Lexer::new(&src);
-    for tok in tokens {
-        println!("{:?}", tok);
+/// new_interpreter creates an interpreter, with the prelude or not.
+fn new_interpreter(no_prelude: bool) -> Interpreter {
+    if no_prelude {
+        Interpreter::without_prelude()
+    } else {
+        Interpreter::new()
     }
 }
 
-fn parse_and_stop(input_file: &Path) {
-    let src = fs::read_to_string(input_file).expect("failed to read input file");
-    let tokens = Lexer::new(&src);
-    let chunks = Parser::new(tokens);
-    for chunk in chunks {
-        println!("{:?}", chunk);
+/// parse_define parses a `name=value` pair, where the value is a literal.
+fn parse_define(s: &str) -> Result<(String, Value), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected `name=value`, found `{}`", s))?;
+    match interpreter::parse_expr(&name.into()) {
+        Ok(Spanned {
+            item: ExprKind::Ident(_),
+            ..
+        }) => {}
+        _ => return Err(format!("`{}` is not a valid name", name)),
     }
+    let value = value.parse().map_err(|e: Error| e.message)?;
+    Ok((name.to_string(), value))
 }
 
-fn run(input_file: &Path) {
-    let src = fs::read_to_string(input_file).expect("failed to read input file");
-    let tokens = Lexer::new(&src);
-    let chunks = Parser::new(tokens);
-    let mut interpreter = interpreter::Interpreter::new();
-    for chunk in chunks {
-        match chunk {
-            DocumentChunk::Raw(r) => print!("{}", r),
-            DocumentChunk::Comment(c) => match interpreter.definition(c) {
-                Err(e) => print!("<!--ERROR: {}-->", e),
-                Ok(()) => {}
-            },
-            DocumentChunk::Interpolate(c) => match interpreter.expr(c) {
-                Err(e) => print!("`ERROR: {}`", e),
-                Ok(v) => print!("{}", v),
-            },
+/// check checks files, knowing about the values which will be defined when running them.
+fn check(input_files: &[PathBuf], defines: &Defines, no_prelude: bool) {
+    let mut problem_count = 0;
+    for input_file in input_files {
+        let src = fs::read_to_string(input_file).expect("failed to read input file");
+        let chunks: Vec<_> = Parser::new(Lexer::new(&src)).map(|c| c.item).collect();
+        let mut interpreter = new_interpreter(no_prelude);
+        if let Err(e) = defines.define(&mut interpreter) {
+            eprintln!("{}", e);
+            process::exit(1);
         }
+        interpreter.set_file(input_file);
+        for problem in interpreter::check(&interpreter, &chunks) {
+            let (line, col) = span::line_col(&src, problem.span.start);
+            println!(
+                "{}:{}:{}: {}",
+                input_file.display(),
+                line,
+                col,
+                problem.message
+            );
+            problem_count += 1;
+        }
+    }
+    if problem_count > 0 {
+        eprintln!("found {} problems", problem_count);
+        process::exit(1);
+    }
+}
+
+fn eval(expr: String, files: &[PathBuf], mut interpreter: Interpreter) {
+    let mut failed = false;
+    for file in files {
+        let src = fs::read_to_string(file).expect("failed to read file to load");
+        interpreter.set_file(file);
+        if let Err(errors) = interpreter.load_definitions(&src) {
+            for e in errors.0 {
+                eprintln!("{}: ERROR: {}", file.display(), e);
+            }
+            failed = true;
+        }
+    }
+    match interpreter.expr(expr) {
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            failed = true;
+        }
+        Ok(v) => println!("{}", v),
+    }
+    if failed {
+        process::exit(1);
     }
 }
 
+fn run(input_file: &Path, defines: &Defines, mut renderer: Renderer, publisher: &Publisher) {
+    if let Err(e) = defines.define(renderer.interpreter()) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    let rendered = renderer
+        .render_file(input_file)
+        .expect("failed to read input file");
+    print!("{}", publisher.publish(rendered.output));
+}
+
 fn main() {
     let args = Command::from_args();
     match args {
-        Command::Lex { input_file } => lex_and_stop(&input_file),
-        Command::Parse { input_file } => parse_and_stop(&input_file),
-        Command::Run { input_file } => run(&input_file),
+        Command::Lex { input_file, format } => dump::lex_and_stop(&input_file, format),
+        Command::Parse {
+            input_file,
+            format,
+            deep,
+        } => dump::parse_and_stop(&input_file, format, deep),
+        Command::Run {
+            input_file,
+            defines,
+            keep_definitions,
+            to,
+            template,
+            no_prelude,
+        } => {
+            let mut renderer = Renderer::with_interpreter(new_interpreter(no_prelude));
+            renderer.set_keep_definitions(keep_definitions);
+            let publisher = Publisher::new(to, template.as_deref());
+            run(&input_file, &defines, renderer, &publisher)
+        }
+        Command::Check {
+            input_files,
+            defines,
+            no_prelude,
+        } => check(&input_files, &defines, no_prelude),
+        Command::Fmt { input_files, check } => fmt::fmt(&input_files, check),
+        Command::Eval {
+            expr,
+            load,
+            no_prelude,
+        } => eval(expr, &load, new_interpreter(no_prelude)),
+        Command::Repl { no_prelude } => repl::repl(new_interpreter(no_prelude)),
+        Command::Watch {
+            input_file,
+            output,
+            defines,
+            keep_definitions,
+            to,
+            template,
+            no_prelude,
+        } => {
+            let mut renderer = Renderer::with_interpreter(new_interpreter(no_prelude));
+            renderer.set_keep_definitions(keep_definitions);
+            let publisher = Publisher::new(to, template.as_deref());
+            watch::watch(
+                &input_file,
+                output.as_deref(),
+                &defines,
+                renderer,
+                &publisher,
+            )
+        }
     }
 }
@@ -1,19 +1,54 @@
 use std::iter::Peekable;
 use std::mem;
 
-use crate::lexer::{Lexer, Token};
+use serde::Serialize;
+
+use crate::lexer::{self, Lexer, Token};
+use crate::span::{Span, Spanned};
 
 /// Code represents a snippet of actual code.
-#[derive(Debug, PartialEq)]
-pub struct Code(pub String);
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Code {
+    pub src: String,
+    /// Where this code starts, in the document containing it.
+    pub offset: usize,
+}
+
+impl Code {
+    /// Create code which doesn't come from any document.
+    pub fn new(src: String) -> Self {
+        Code { src, offset: 0 }
+    }
+}
+
+impl From<String> for Code {
+    fn from(src: String) -> Self {
+        Code::new(src)
+    }
+}
+
+impl From<&str> for Code {
+    fn from(src: &str) -> Self {
+        Code::new(src.to_string())
+    }
+}
 
 /// DocumentChunk represent an individual chunk composing our document.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value")]
 pub enum DocumentChunk {
     /// A commented bit of code, which should be executed, but the result discarded.
     Comment(Code),
     /// A interpolated bit of code, which should be executed and inlined.
     Interpolate(Code),
+    /// A fenced block of code, tagged `wahl`, which should be executed and replaced by its output.
+    ///
+    /// Blocks tagged `wahl-show` are kept as they are in the source, followed by their output.
+    Block {
+        code: Code,
+        source: String,
+        show: bool,
+    },
     /// A raw chunk of document which doesn't need to be executed at all.
     Raw(String),
 }
@@ -22,7 +57,12 @@ pub enum DocumentChunk {
 pub struct Parser<'a> {
     tokens: Peekable<Lexer<'a>>,
     acc: String,
-    produced: Option<DocumentChunk>,
+    /// Where the raw chunk we're accumulating starts and ends.
+    ///
+    /// The end can't be found from the start, since escaped text is shorter than its source.
+    acc_start: usize,
+    acc_end: usize,
+    produced: Option<Spanned<DocumentChunk>>,
 }
 
 impl<'a> Parser<'a> {
@@ -30,19 +70,25 @@ impl<'a> Parser<'a> {
         Self {
             tokens: tokens.peekable(),
             acc: String::new(),
+            acc_start: 0,
+            acc_end: 0,
             produced: None,
         }
     }
 
-    fn comment(&mut self) -> Option<Code> {
+    /// comment reads code until the end of a comment, starting at a given position.
+    ///
+    /// This also returns the position of the end of the comment.
+    fn comment(&mut self, offset: usize) -> Option<(Code, usize)> {
         let mut acc = String::new();
         while let Some(peek) = self.tokens.peek() {
-            match peek {
+            match peek.item {
                 Token::CommentClose => {
+                    let end = peek.span.end;
                     self.tokens.next();
-                    return Some(Code(acc));
+                    return Some((Code { src: acc, offset }, end));
                 }
-                t => {
+                ref t => {
                     t.push_to(&mut acc);
                     self.tokens.next();
                 }
@@ -51,35 +97,69 @@ impl<'a> Parser<'a> {
         None
     }
 
-    fn interpolate(&mut self) -> Code {
+    /// interpolate reads code until the next tick, starting at a given position.
+    ///
+    /// This also returns the position of the end of the interpolation.
+    fn interpolate(&mut self, offset: usize) -> (Code, usize) {
         let mut acc = String::new();
+        let mut end = offset;
         while let Some(peek) = self.tokens.peek() {
-            match peek {
+            end = peek.span.end;
+            match peek.item {
                 Token::Tick => {
                     self.tokens.next();
-                    return Code(acc);
+                    return (Code { src: acc, offset }, end);
                 }
-                t => {
+                ref t => {
                     t.push_to(&mut acc);
                     self.tokens.next();
                 }
             }
         }
-        Code(acc)
+        (Code { src: acc, offset }, end)
     }
 
-    fn take_raw(&mut self) -> Option<DocumentChunk> {
+    /// block reads code until the fence closing a block, starting with the fence opening it.
+    ///
+    /// This returns the code, along with the source of the whole block and its span.
+    /// Like in Markdown, a block that never gets closed runs until the end of the document.
+    fn block(&mut self, open: Spanned<Token>) -> (Code, String, Span) {
+        let mut source = String::new();
+        open.item.push_to(&mut source);
+        let mut end = open.span.end;
+        if let Some(Token::Newline) = self.tokens.peek().map(|t| &t.item) {
+            source.push('\n');
+            end += 1;
+            self.tokens.next();
+        }
+        let mut code = Code {
+            src: String::new(),
+            offset: end,
+        };
+        for tok in self.tokens.by_ref() {
+            tok.item.push_to(&mut source);
+            end = tok.span.end;
+            match tok.item {
+                Token::Fence(_) => break,
+                t => t.push_to(&mut code.src),
+            }
+        }
+        (code, source, Span::new(open.span.start, end))
+    }
+
+    fn take_raw(&mut self) -> Option<Spanned<DocumentChunk>> {
         if self.acc.is_empty() {
             None
         } else {
             let acc = mem::take(&mut self.acc);
-            Some(DocumentChunk::Raw(acc))
+            let span = Span::new(self.acc_start, self.acc_end);
+            Some(Spanned::new(DocumentChunk::Raw(acc), span))
         }
     }
 }
 
 impl<'a> Iterator for Parser<'a> {
-    type Item = DocumentChunk;
+    type Item = Spanned<DocumentChunk>;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
@@ -92,24 +172,44 @@ impl<'a> Iterator for Parser<'a> {
                 Some(tok) => tok,
             };
 
-            match next {
+            match next.item {
                 // TODO: Report an error here
                 Token::CommentClose => {}
                 Token::CommentOpen => {
-                    if let Some(code) = self.comment() {
-                        self.produced = Some(DocumentChunk::Comment(code))
+                    if let Some((code, end)) = self.comment(next.span.end) {
+                        let span = Span::new(next.span.start, end);
+                        self.produced = Some(Spanned::new(DocumentChunk::Comment(code), span))
                     }
                     if let Some(chunk) = self.take_raw() {
                         return Some(chunk);
                     }
                 }
                 Token::Tick => {
-                    self.produced = Some(DocumentChunk::Interpolate(self.interpolate()));
+                    let (code, end) = self.interpolate(next.span.end);
+                    let span = Span::new(next.span.start, end);
+                    self.produced = Some(Spanned::new(DocumentChunk::Interpolate(code), span));
+                    if let Some(chunk) = self.take_raw() {
+                        return Some(chunk);
+                    }
+                }
+                Token::Fence(ref fence)
+                    if matches!(lexer::fence_info(fence), "wahl" | "wahl-show") =>
+                {
+                    let show = lexer::fence_info(fence) == "wahl-show";
+                    let (code, source, span) = self.block(next);
+                    let block = DocumentChunk::Block { code, source, show };
+                    self.produced = Some(Spanned::new(block, span));
                     if let Some(chunk) = self.take_raw() {
                         return Some(chunk);
                     }
                 }
-                t => t.push_to(&mut self.acc)
+                t => {
+                    if self.acc.is_empty() {
+                        self.acc_start = next.span.start;
+                    }
+                    self.acc_end = next.span.end;
+                    t.push_to(&mut self.acc)
+                }
             }
         }
     }
@@ -0,0 +1,56 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use wahlbergdown::html;
+
+/// The format of the output of a document.
+#[derive(Clone, Copy, Debug)]
+pub enum To {
+    Markdown,
+    Html,
+}
+
+impl FromStr for To {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" => Ok(To::Markdown),
+            "html" => Ok(To::Html),
+            _ => Err(format!(
+                "unknown format `{}`, expected `markdown` or `html`",
+                s
+            )),
+        }
+    }
+}
+
+/// A Publisher turns the Markdown produced by documents into their final output.
+#[derive(Clone, Debug)]
+pub struct Publisher {
+    to: To,
+    /// The page template HTML goes in, if any.
+    template: Option<String>,
+}
+
+impl Publisher {
+    pub fn new(to: To, template_file: Option<&Path>) -> Self {
+        let template = template_file
+            .map(|path| fs::read_to_string(path).expect("failed to read template file"));
+        Publisher { to, template }
+    }
+
+    pub fn publish(&self, markdown: String) -> String {
+        match self.to {
+            To::Markdown => markdown,
+            To::Html => {
+                let content = html::render(&markdown);
+                match &self.template {
+                    None => content,
+                    Some(template) => html::page(template, &content),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::fs;
+
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+
+use wahlbergdown::interpreter::{self, Interpreter};
+use wahlbergdown::Code;
+
+const PROMPT: &str = "> ";
+/// The prompt we use while waiting for the rest of an unfinished input.
+const CONTINUE_PROMPT: &str = ". ";
+
+/// A Repl holds the state of an interactive session.
+struct Repl {
+    interpreter: Interpreter,
+}
+
+impl Repl {
+    fn new(interpreter: Interpreter) -> Self {
+        Repl { interpreter }
+    }
+
+    fn load(&mut self, path: &str) {
+        let src = match fs::read_to_string(path) {
+            Err(e) => {
+                println!("failed to read {}: {}", path, e);
+                return;
+            }
+            Ok(src) => src,
+        };
+        // The files the loaded file imports are relative to it.
+        self.interpreter.set_file(path);
+        let result = self.interpreter.load_definitions(&src);
+        self.interpreter.clear_file();
+        if let Err(errors) = result {
+            for e in errors.0 {
+                println!("ERROR: {}", e);
+            }
+        }
+    }
+
+    fn command(&mut self, command: &str) {
+        let mut parts = command.splitn(2, char::is_whitespace);
+        match (parts.next(), parts.next().map(str::trim)) {
+            (Some("load"), Some(path)) if !path.is_empty() => self.load(path),
+            _ => println!("unknown command `:{}`, try `:load <file>`", command),
+        }
+    }
+
+    fn input(&mut self, input: String) {
+        let code = Code::new(input);
+        if interpreter::is_definition(&code) {
+            if let Err(e) = self.interpreter.definition(code) {
+                println!("ERROR: {}", e);
+            }
+        } else {
+            match self.interpreter.expr(code) {
+                Err(e) => println!("ERROR: {}", e),
+                Ok(v) => println!("{}", v),
+            }
+        }
+    }
+}
+
+/// repl runs an interactive session with an interpreter, until the user closes it.
+pub fn repl(interpreter: Interpreter) {
+    let mut editor = Editor::<()>::new();
+    let mut repl = Repl::new(interpreter);
+    let mut acc = String::new();
+    loop {
+        let prompt = if acc.is_empty() {
+            PROMPT
+        } else {
+            CONTINUE_PROMPT
+        };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C abandons the current input, instead of the whole session
+            Err(ReadlineError::Interrupted) => {
+                acc.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("failed to read input: {}", e);
+                break;
+            }
+        };
+        if acc.is_empty() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(command) = line.trim().strip_prefix(':') {
+                editor.add_history_entry(line.as_str());
+                repl.command(command);
+                continue;
+            }
+        } else {
+            acc.push('\n');
+        }
+        acc.push_str(&line);
+        if interpreter::incomplete(&Code::new(acc.clone())) {
+            continue;
+        }
+        editor.add_history_entry(acc.as_str());
+        repl.input(std::mem::take(&mut acc));
+    }
+}
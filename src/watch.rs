@@ -0,0 +1,128 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use wahlbergdown::Renderer;
+
+use crate::publish::Publisher;
+use crate::Defines;
+
+/// How long we wait between two checks of the watched files.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// A Watcher polls a set of files, noticing when any of them changes.
+#[derive(Debug)]
+struct Watcher {
+    /// Each file, along with the last modification time we saw for it.
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl Watcher {
+    fn new(files: impl IntoIterator<Item = PathBuf>) -> Self {
+        Watcher {
+            files: files
+                .into_iter()
+                .map(|f| {
+                    let last = modified(&f);
+                    (f, last)
+                })
+                .collect(),
+        }
+    }
+
+    /// add starts watching more files, if we aren't already.
+    fn add(&mut self, files: impl IntoIterator<Item = PathBuf>) {
+        for f in files {
+            if self.files.iter().all(|(path, _)| *path != f) {
+                let last = modified(&f);
+                self.files.push((f, last));
+            }
+        }
+    }
+
+    /// changed checks every file, returning true if any of them was modified since the last check.
+    fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, last) in &mut self.files {
+            let now = modified(path);
+            if now != *last {
+                *last = now;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// rebuild renders a file, returning the files it imports and includes, and the data files.
+fn rebuild(
+    input_file: &Path,
+    output: Option<&Path>,
+    defines: &Defines,
+    renderer: &Renderer,
+    publisher: &Publisher,
+) -> Vec<PathBuf> {
+    let start = Instant::now();
+    let mut renderer = renderer.clone();
+    if let Err(e) = defines.define(renderer.interpreter()) {
+        eprintln!("{}", e);
+        return defines.data.clone();
+    }
+    let rendered = match renderer.render_file(input_file) {
+        Err(e) => {
+            eprintln!("failed to read {}: {}", input_file.display(), e);
+            return Vec::new();
+        }
+        Ok(rendered) => rendered,
+    };
+    let published = publisher.publish(rendered.output);
+    match output {
+        None => print!("{}", published),
+        Some(path) => {
+            if let Err(e) = fs::write(path, &published) {
+                eprintln!("failed to write {}: {}", path.display(), e);
+            }
+        }
+    }
+    eprintln!(
+        "rendered {} in {:.2?} ({} errors)",
+        input_file.display(),
+        start.elapsed(),
+        rendered.errors.len()
+    );
+    for e in &rendered.errors {
+        eprintln!("  error: {}", e);
+    }
+    renderer
+        .interpreter()
+        .loaded_files()
+        .map(PathBuf::from)
+        .chain(defines.data.iter().cloned())
+        .collect()
+}
+
+/// watch runs a file each time it, or a file it uses, gets modified, until the process is killed.
+///
+/// Each run starts from a copy of the given renderer, with the values defined again,
+/// so that changes to data files show up too.
+pub fn watch(
+    input_file: &Path,
+    output: Option<&Path>,
+    defines: &Defines,
+    renderer: Renderer,
+    publisher: &Publisher,
+) {
+    let mut watcher = Watcher::new(vec![input_file.to_path_buf()]);
+    watcher.add(rebuild(input_file, output, defines, &renderer, publisher));
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        if watcher.changed() {
+            watcher.add(rebuild(input_file, output, defines, &renderer, publisher));
+        }
+    }
+}